@@ -0,0 +1,79 @@
+//! 整形処理のベンチマーク
+//!
+//! ガイドの全ての Markdown ファイル（リポジトリの `src/`）を整形する時間を測ります。
+//! 行の挿入・削除のコストはファイルの長さに対して二次的に増えるため、
+//! 全ファイルを連結した1つの大きな文書でも測ります。
+//!
+//! ```bash
+//! cargo bench --bench format
+//! ```
+
+use std::fs;
+use std::hint::black_box;
+use std::path::{Path, PathBuf};
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use sembr::{Formatter, Options};
+
+/// ガイドの全ての Markdown ファイルの内容を読み込む
+fn corpus() -> Vec<String> {
+    let mut directories = vec![Path::new(env!("CARGO_MANIFEST_DIR")).join("../../src")];
+    let mut paths: Vec<PathBuf> = Vec::new();
+    while let Some(directory) = directories.pop() {
+        for entry in fs::read_dir(directory).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                directories.push(path);
+            } else if path.extension().is_some_and(|extension| extension == "md") {
+                paths.push(path);
+            }
+        }
+    }
+    // 実行ごとに同じ順序で測る
+    paths.sort();
+    paths.into_iter().map(|path| fs::read_to_string(path).unwrap()).collect()
+}
+
+/// ファイルごとの整形と、連結した大きな文書の整形を測る
+fn bench_format(c: &mut Criterion) {
+    let files = corpus();
+    let concatenated = files.join("\n");
+    let formatter = Formatter::new(Options::default()).unwrap();
+    let mut options = Options::default();
+    options.max_line_length = Some(100);
+    let max_line_length = Formatter::new(options).unwrap();
+
+    let mut group = c.benchmark_group("format");
+    group.throughput(Throughput::Bytes(concatenated.len() as u64));
+    group.sample_size(10);
+
+    group.bench_function("src", |b| {
+        b.iter(|| {
+            for content in &files {
+                black_box(formatter.format_str(black_box(content)));
+            }
+        });
+    });
+    group.bench_function("src/max-line-length", |b| {
+        b.iter(|| {
+            for content in &files {
+                black_box(max_line_length.format_str(black_box(content)));
+            }
+        });
+    });
+    group.bench_function("concatenated", |b| {
+        b.iter(|| black_box(formatter.format_str(black_box(&concatenated))));
+    });
+    group.bench_function("check/src", |b| {
+        b.iter(|| {
+            for content in &files {
+                black_box(formatter.check_str(black_box(content)));
+            }
+        });
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_format);
+criterion_main!(benches);
@@ -0,0 +1,242 @@
+//! 適合済みのファイルのキャッシュ
+//!
+//! pre-commit や CI では、200 以上のページのうち1ページだけを編集して実行することがほとんどです。
+//! 前回の実行で適合していたファイルの内容のハッシュを記録しておき、
+//! 内容、sembr のバージョン、適用する整形規則がすべて同じファイルは整形を省略します。
+//!
+//! キャッシュは設定ファイルのディレクトリの [`CACHE_FILE_NAME`] に保存します。
+//! 設定ファイルがない場合は、実行したディレクトリに不要なファイルを残さないように
+//! キャッシュを使いません。
+//! `--no-cache` で無効にできます。
+//!
+//! # ファイル形式
+//! 1行に1ファイルずつ、キーと設定ファイルのディレクトリからの相対パスをタブで区切って記録します。
+//! 走査の起点によらず（`src/x.md` と `./src/x.md`）、同じファイルは同じ行になります。
+//! ```text
+//! <キー><TAB><パス>
+//! ```
+//!
+//! 保存する前に、走査したディレクトリの中で今回処理しなかったファイルと、
+//! 削除されたファイルの記録を取り除きます（[`Cache::prune`]）。
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+
+use sembr::Options;
+
+/// キャッシュファイルの名前
+pub const CACHE_FILE_NAME: &str = ".sembr-cache";
+
+/// 適合済みのファイルのキャッシュ
+#[derive(Debug)]
+pub struct Cache {
+    /// キャッシュファイルを置くディレクトリ（正規化済み、記録するパスの基準）
+    root: PathBuf,
+    /// ルートからの相対パスから、適合していたときのキーへの対応
+    entries: HashMap<PathBuf, String>,
+    /// 今回の実行で結果を記録したファイル（ルートからの相対パス）
+    seen: HashSet<PathBuf>,
+}
+
+impl Cache {
+    /// ディレクトリのキャッシュファイルを読み込む
+    ///
+    /// ファイルがない場合や壊れた行は無視します（該当するファイルを整形し直すだけです）。
+    pub fn load(root: &Path) -> Self {
+        let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+        let entries = fs::read_to_string(root.join(CACHE_FILE_NAME))
+            .map(|text| {
+                text.lines()
+                    .filter_map(|line| line.split_once('\t'))
+                    .map(|(key, path)| (PathBuf::from(path), key.to_owned()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self { root, entries, seen: HashSet::new() }
+    }
+
+    /// キャッシュファイルのパス
+    pub fn path(&self) -> PathBuf {
+        self.root.join(CACHE_FILE_NAME)
+    }
+
+    /// キャッシュファイルに書き込む
+    ///
+    /// 同時に実行された sembr と競合しても壊れたファイルが残らないように、
+    /// 一時ファイルに書き込んでから置き換えます。
+    pub fn save(&self) -> io::Result<()> {
+        let mut entries: Vec<_> = self.entries.iter().collect();
+        entries.sort();
+        let text: String =
+            entries.into_iter().map(|(path, key)| format!("{key}\t{}\n", path.display())).collect();
+
+        let path = self.path();
+        let temporary = path.with_extension(format!("{}.tmp", std::process::id()));
+        fs::write(&temporary, text)?;
+        fs::rename(&temporary, path)
+    }
+
+    /// ファイルが前回の実行で同じキーのまま適合していたかどうか
+    pub fn is_compliant(&self, path: &Path, key: &str) -> bool {
+        self.relative(path)
+            .and_then(|relative| self.entries.get(&relative))
+            .is_some_and(|cached| cached == key)
+    }
+
+    /// ファイルの結果を記録する
+    ///
+    /// 適合していれば `Some(key)`、そうでなければ `None` を渡します。
+    /// 適合していないファイルの古い記録は削除します。
+    /// ルートの外のファイルは記録しません。
+    pub fn update(&mut self, path: &Path, key: Option<String>) {
+        let Some(relative) = self.relative(path) else {
+            return;
+        };
+        match key {
+            Some(key) => self.entries.insert(relative.clone(), key),
+            None => self.entries.remove(&relative),
+        };
+        self.seen.insert(relative);
+    }
+
+    /// 不要になった記録を取り除く
+    ///
+    /// - `walked` 以下のファイルで、今回の実行で記録しなかったもの（削除・移動されたファイルや、
+    ///   対象外になったファイル）
+    /// - `walked` の外のファイルで、既に存在しないもの
+    ///
+    /// 一部のディレクトリだけを処理した場合でも、それ以外のファイルの記録は残ります。
+    pub fn prune(&mut self, walked: &Path) {
+        let walked = self.relative(walked);
+        self.entries.retain(|path, _| {
+            self.seen.contains(path)
+                || (walked.as_ref().is_none_or(|walked| !path.starts_with(walked))
+                    && self.root.join(path).exists())
+        });
+    }
+
+    /// ルートからの相対パスを求める（ルートの外なら `None`）
+    fn relative(&self, path: &Path) -> Option<PathBuf> {
+        let path = path.canonicalize().ok()?;
+        path.strip_prefix(&self.root).ok().map(Path::to_path_buf)
+    }
+}
+
+/// ファイルの内容と整形規則から、キャッシュのキーを求める
+///
+/// 内容、sembr のバージョン、整形規則のいずれかが変わればキーも変わります。
+/// バージョンには、開発中のビルドを区別するため実行ファイルの大きさと更新日時も含めます。
+///
+/// # 戻り値
+/// BLAKE3 ハッシュの16進表現
+pub fn key(content: &str, options: &Options) -> String {
+    let mut hasher = blake3::Hasher::new();
+    for part in [version().as_str(), &format!("{options:?}"), content] {
+        hasher.update(part.as_bytes());
+        // 区切りを入れて、部分の境界が異なる入力を区別する
+        hasher.update(&[0]);
+    }
+    hasher.finalize().to_hex().to_string()
+}
+
+/// sembr のバージョン（実行ファイルの識別情報を含む）
+fn version() -> String {
+    let build = env::current_exe()
+        .and_then(fs::metadata)
+        .ok()
+        .map(|metadata| {
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+                .unwrap_or_default();
+            format!("{}-{}", metadata.len(), modified.as_nanos())
+        })
+        .unwrap_or_default();
+    format!("{}+{build}", env!("CARGO_PKG_VERSION"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use sembr::Options;
+
+    use super::{CACHE_FILE_NAME, Cache, key};
+
+    /// キャッシュの記録、保存、読み込みのテスト
+    #[test]
+    fn test_cache() {
+        let directory = std::env::temp_dir().join(format!("sembr-cache-{}", std::process::id()));
+        fs::create_dir_all(directory.join("src")).unwrap();
+        for file in ["src/a.md", "src/b.md", "src/c.md", "d.md"] {
+            fs::write(directory.join(file), "").unwrap();
+        }
+
+        let options = Options::default();
+        let a = directory.join("src/a.md");
+        let b = directory.join("src/b.md");
+
+        let mut cache = Cache::load(&directory);
+        cache.update(&a, Some(key("a.\n", &options)));
+        cache.update(&b, Some(key("b.\n", &options)));
+        assert!(cache.is_compliant(&a, &key("a.\n", &options)));
+
+        // 同じファイルは、パスの表記によらず同じ記録になる
+        assert!(cache.is_compliant(&directory.join("src/./a.md"), &key("a.\n", &options)));
+
+        // 内容や整形規則が変われば、キーも変わる
+        assert!(!cache.is_compliant(&a, &key("a. b.\n", &options)));
+        let mut options_80 = Options::default();
+        options_80.line_length_limit = 80;
+        assert!(!cache.is_compliant(&a, &key("a.\n", &options_80)));
+
+        // 適合しなくなったファイルの記録は削除する
+        cache.update(&b, None);
+        assert!(!cache.is_compliant(&b, &key("b.\n", &options)));
+
+        cache.save().unwrap();
+        let text = fs::read_to_string(directory.join(CACHE_FILE_NAME)).unwrap();
+        assert_eq!(format!("{}\tsrc/a.md\n", key("a.\n", &options)), text);
+
+        let loaded = Cache::load(&directory);
+        assert!(loaded.is_compliant(&a, &key("a.\n", &options)));
+        assert!(!loaded.is_compliant(&b, &key("b.\n", &options)));
+
+        // 読み込めないキャッシュは空として扱う
+        assert!(!Cache::load(&directory.join("missing")).is_compliant(&a, "key"));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    /// 不要になった記録を取り除くテスト
+    #[test]
+    fn test_cache_prune() {
+        let directory =
+            std::env::temp_dir().join(format!("sembr-cache-prune-{}", std::process::id()));
+        fs::create_dir_all(directory.join("src")).unwrap();
+        for file in ["src/a.md", "src/b.md", "d.md", "e.md"] {
+            fs::write(directory.join(file), "").unwrap();
+        }
+
+        let mut cache = Cache::load(&directory);
+        for file in ["src/a.md", "src/b.md", "d.md", "e.md"] {
+            cache.update(&directory.join(file), Some("key".to_owned()));
+        }
+        cache.save().unwrap();
+
+        // `src` だけを処理し、`src/b.md` と `e.md` を削除した
+        fs::remove_file(directory.join("src/b.md")).unwrap();
+        fs::remove_file(directory.join("e.md")).unwrap();
+        let mut cache = Cache::load(&directory);
+        cache.update(&directory.join("src/a.md"), Some("key".to_owned()));
+        cache.prune(&directory.join("src"));
+        cache.save().unwrap();
+
+        let text = fs::read_to_string(directory.join(CACHE_FILE_NAME)).unwrap();
+        assert_eq!("key\td.md\nkey\tsrc/a.md\n", text);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,458 @@
+//! プロジェクト設定ファイル（`sembr.toml`）
+//!
+//! CI、pre-commit、エディタ連携が同じ設定を共有できるように、
+//! 整形規則やチェック対象のファイルを `sembr.toml` に記述します。
+//! 設定ファイルは、チェック対象のパスから親ディレクトリへ向かって探索します。
+//!
+//! # 設定の優先順位（後のものほど優先）
+//! 1. 組み込みの既定値（[`Options::default`]）
+//! 2. 設定ファイルの最上位の項目
+//! 3. パスが一致する `[[overrides]]` の項目（記述順）
+//! 4. コマンドライン引数
+//!
+//! # 設定例
+//! ```toml
+//! line-length-limit = 100
+//! include = ["src/**/*.md"]
+//! never-split-after = ['(^|\s)r\?']
+//!
+//! [[overrides]]
+//! path = "src/appendix"
+//! line-length-limit = 120
+//! ```
+
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::Deserialize;
+
+use crate::{CjkLatinSpacing, Error, Formatter, LengthUnit, LineEnding, Options, Result};
+
+/// 設定ファイルの名前
+pub const CONFIG_FILE_NAME: &str = "sembr.toml";
+
+/// 整形規則の設定値
+///
+/// 設定ファイルの最上位、`[[overrides]]` の各項目、コマンドライン引数で共通に使います。
+/// 指定されなかった項目（`None`）は、優先度の低い設定の値を引き継ぎます。
+/// 設定ファイルの未知の項目（綴りの誤りなど）はエラーにします（`ConfigFile::parse`）。
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Rules {
+    /// 行を結合する際の最大行長
+    pub line_length_limit: Option<usize>,
+
+    /// 行の長さを数える単位
+    pub line_length_unit: Option<LengthUnit>,
+
+    /// CJK/ラテン境界で行を結合する際の空白の扱い
+    pub cjk_latin_spacing: Option<CjkLatinSpacing>,
+
+    /// 文末とみなさない略語（既定のリストを置き換える）
+    pub abbreviations: Option<Vec<String>>,
+
+    /// 追加の文末記号
+    pub extra_terminators: Option<Vec<String>>,
+
+    /// 直前のテキストがマッチした場合に分割しない正規表現
+    pub never_split_after: Option<Vec<String>>,
+
+    /// 1行の最大長（超える文は節の境界でも分割する）
+    pub max_line_length: Option<usize>,
+
+    /// 整形結果の改行コード
+    pub line_ending: Option<LineEnding>,
+
+    /// 末尾に改行のないファイルに改行を追加するかどうか
+    pub insert_final_newline: Option<bool>,
+
+    /// 先頭の BOM を取り除くかどうか
+    pub strip_bom: Option<bool>,
+}
+
+impl Rules {
+    /// 指定された項目で設定を上書きする
+    fn apply(&self, options: &mut Options) {
+        if let Some(line_length_limit) = self.line_length_limit {
+            options.line_length_limit = line_length_limit;
+        }
+        if let Some(line_length_unit) = self.line_length_unit {
+            options.line_length_unit = line_length_unit;
+        }
+        if let Some(cjk_latin_spacing) = self.cjk_latin_spacing {
+            options.cjk_latin_spacing = cjk_latin_spacing;
+        }
+        if let Some(abbreviations) = &self.abbreviations {
+            options.abbreviations.clone_from(abbreviations);
+        }
+        if let Some(extra_terminators) = &self.extra_terminators {
+            options.extra_terminators.clone_from(extra_terminators);
+        }
+        if let Some(never_split_after) = &self.never_split_after {
+            options.never_split_after.clone_from(never_split_after);
+        }
+        if let Some(max_line_length) = self.max_line_length {
+            options.max_line_length = Some(max_line_length);
+        }
+        if let Some(line_ending) = self.line_ending {
+            options.line_ending = line_ending;
+        }
+        if let Some(insert_final_newline) = self.insert_final_newline {
+            options.insert_final_newline = insert_final_newline;
+        }
+        if let Some(strip_bom) = self.strip_bom {
+            options.strip_bom = strip_bom;
+        }
+    }
+}
+
+/// 設定ファイルの内容
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct ConfigFile {
+    /// 全ファイルに適用する整形規則
+    #[serde(flatten)]
+    rules: Rules,
+
+    /// チェック対象とするファイルの glob（空なら全ての .md ファイル）
+    include: Vec<String>,
+
+    /// チェック対象から除外するファイルの glob
+    exclude: Vec<String>,
+
+    /// ディレクトリごとの整形規則
+    overrides: Vec<OverrideFile>,
+}
+
+impl ConfigFile {
+    /// 設定ファイルの内容を読み込む
+    ///
+    /// `#[serde(flatten)]` した [`Rules`] には未知の項目が渡されないため、
+    /// `deny_unknown_fields` だけでは綴りを誤った項目（`line-lenght-limit` など）を検出できません。
+    /// 整形規則以外の項目を取り除いた表を [`Rules`] として読み直し、未知の項目を検出します。
+    ///
+    /// `[[overrides]]` のパスは、`./src/appendix` や `src/appendix/` と書いても
+    /// `src/appendix` と同じく一致するように、`.` の要素を取り除いて正規化します。
+    fn parse(text: &str) -> std::result::Result<Self, toml::de::Error> {
+        let mut file: Self = toml::from_str(text)?;
+        for r#override in &mut file.overrides {
+            r#override.path = r#override
+                .path
+                .components()
+                .filter(|component| *component != Component::CurDir)
+                .collect();
+        }
+
+        let mut table: toml::Table = toml::from_str(text)?;
+        let overrides = table.remove("overrides");
+        table.remove("include");
+        table.remove("exclude");
+        Rules::deserialize(table)?;
+
+        let overrides = overrides.as_ref().and_then(toml::Value::as_array).into_iter().flatten();
+        for r#override in overrides.filter_map(toml::Value::as_table) {
+            let mut table = r#override.clone();
+            table.remove("path");
+            Rules::deserialize(table)?;
+        }
+
+        Ok(file)
+    }
+}
+
+/// 設定ファイルの `[[overrides]]` の項目
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct OverrideFile {
+    /// 適用するディレクトリまたはファイル（設定ファイルからの相対パス）
+    path: PathBuf,
+
+    /// そのディレクトリ以下に適用する整形規則
+    #[serde(flatten)]
+    rules: Rules,
+}
+
+/// 読み込み済みの設定
+#[derive(Debug)]
+pub struct Config {
+    /// 設定ファイルのあるディレクトリ（glob とパスの基準、設定ファイルがなければ `None`）
+    root: Option<PathBuf>,
+
+    /// 全ファイルに適用する整形規則
+    rules: Rules,
+
+    /// チェック対象とするファイル（`None` なら全て）
+    include: Option<GlobSet>,
+
+    /// チェック対象から除外するファイル
+    exclude: GlobSet,
+
+    /// ディレクトリごとの整形規則
+    overrides: Vec<OverrideFile>,
+}
+
+impl Default for Config {
+    /// 設定ファイルがない場合の設定（全て既定値）
+    fn default() -> Self {
+        Self {
+            root: None,
+            rules: Rules::default(),
+            include: None,
+            exclude: GlobSet::empty(),
+            overrides: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// `start` から親ディレクトリへ向かって設定ファイルを探索して読み込む
+    ///
+    /// Git リポジトリのルート（`.git` のあるディレクトリ）より上は探索しません。
+    /// 設定ファイルが見つからなければ、既定の設定を返します。
+    pub fn discover(start: &Path) -> Result<Self> {
+        let start = start
+            .canonicalize()
+            .map_err(|source| Error::Io { path: start.to_path_buf(), source })?;
+
+        for directory in start.ancestors().filter(|path| path.is_dir()) {
+            let candidate = directory.join(CONFIG_FILE_NAME);
+            if candidate.is_file() {
+                return Self::load(&candidate);
+            }
+            if directory.join(".git").exists() {
+                break;
+            }
+        }
+
+        Ok(Self::default())
+    }
+
+    /// 指定された設定ファイルを読み込む
+    ///
+    /// # エラー
+    /// - ファイルが読めない場合（[`Error::Io`]）
+    /// - TOML として不正な場合や、未知の項目を含む場合（[`Error::InvalidConfig`]）
+    /// - glob や正規表現が不正な場合（[`Error::InvalidGlob`]、[`Error::InvalidPattern`]）
+    pub fn load(path: &Path) -> Result<Self> {
+        let io_error = |source| Error::Io { path: path.to_path_buf(), source };
+        let text = fs::read_to_string(path).map_err(io_error)?;
+        let file = ConfigFile::parse(&text)
+            .map_err(|source| Error::InvalidConfig { path: path.to_path_buf(), source })?;
+
+        // 正規表現の誤りをファイル単位の処理の前に検出する
+        for rules in std::iter::once(&file.rules).chain(file.overrides.iter().map(|o| &o.rules)) {
+            let mut options = Options::default();
+            rules.apply(&mut options);
+            Formatter::new(options)?;
+        }
+
+        let root = path
+            .canonicalize()
+            .map_err(io_error)?
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| PathBuf::from("."));
+
+        Ok(Self {
+            root: Some(root),
+            rules: file.rules,
+            include: if file.include.is_empty() { None } else { Some(glob_set(&file.include)?) },
+            exclude: glob_set(&file.exclude)?,
+            overrides: file.overrides,
+        })
+    }
+
+    /// 設定ファイルのあるディレクトリ（設定ファイルがなければ `None`）
+    pub fn root(&self) -> Option<&Path> {
+        self.root.as_deref()
+    }
+
+    /// ファイルがチェック対象かどうか
+    ///
+    /// `include` のいずれかに一致し、`exclude` のいずれにも一致しない場合に `true` です。
+    /// 設定ファイルのディレクトリの外にあるファイルは、`include` が指定されていれば対象外です。
+    pub fn is_included(&self, path: &Path) -> bool {
+        let Some(relative) = self.relative(path) else {
+            return self.include.is_none();
+        };
+        self.include.as_ref().is_none_or(|include| include.is_match(&relative))
+            && !self.exclude.is_match(&relative)
+    }
+
+    /// ファイルに適用する整形規則を求める
+    ///
+    /// 既定値、設定ファイル、一致する `[[overrides]]`、`cli` の順に上書きします。
+    /// 正規表現の検証は [`Formatter::new`] で行います。
+    pub fn options_for(&self, path: &Path, cli: &Rules) -> Options {
+        let mut options = Options::default();
+        self.rules.apply(&mut options);
+
+        if let Some(relative) = self.relative(path) {
+            for r#override in &self.overrides {
+                if relative.starts_with(&r#override.path) {
+                    r#override.rules.apply(&mut options);
+                }
+            }
+        }
+
+        cli.apply(&mut options);
+        options
+    }
+
+    /// 設定ファイルのディレクトリからの相対パスを求める
+    ///
+    /// エディタで保存前の新しいファイルも扱えるように、
+    /// ファイルが存在しなければ親ディレクトリを正規化して求めます。
+    fn relative(&self, path: &Path) -> Option<PathBuf> {
+        let root = self.root.as_deref().unwrap_or(Path::new(".")).canonicalize().ok()?;
+        let path = path.canonicalize().ok().or_else(|| {
+            let parent = path.parent().filter(|parent| !parent.as_os_str().is_empty());
+            Some(parent.unwrap_or(Path::new(".")).canonicalize().ok()?.join(path.file_name()?))
+        })?;
+        path.strip_prefix(root).ok().map(Path::to_path_buf)
+    }
+}
+
+/// glob のリストから [`GlobSet`] を作成する
+fn glob_set(globs: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(
+            Glob::new(glob).map_err(|source| Error::InvalidGlob { glob: glob.clone(), source })?,
+        );
+    }
+    builder.build().map_err(|source| Error::InvalidGlob { glob: globs.join(", "), source })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::{CONFIG_FILE_NAME, Config, ConfigFile, Rules};
+    use crate::{Error, LengthUnit};
+
+    /// 設定ファイルの探索と、優先順位に従った整形規則の解決のテスト
+    #[test]
+    fn test_config() {
+        let root = std::env::temp_dir().join(format!("sembr-config-{}", std::process::id()));
+        fs::create_dir_all(root.join("docs/appendix")).unwrap();
+        fs::create_dir_all(root.join("docs/drafts")).unwrap();
+        fs::write(root.join(".git"), "").unwrap();
+        fs::write(
+            root.join(CONFIG_FILE_NAME),
+            r#"
+line-length-limit = 80
+line-length-unit = "chars"
+abbreviations = ["e.g."]
+include = ["docs/**/*.md"]
+exclude = ["docs/drafts/**"]
+
+[[overrides]]
+path = "docs/appendix"
+line-length-limit = 120
+max-line-length = 150
+"#,
+        )
+        .unwrap();
+        for file in ["docs/a.md", "docs/appendix/b.md", "docs/drafts/c.md", "d.md"] {
+            fs::write(root.join(file), "").unwrap();
+        }
+
+        // 子ディレクトリから探索できる
+        let config = Config::discover(&root.join("docs/appendix")).unwrap();
+        assert_eq!(Some(root.canonicalize().unwrap().as_path()), config.root());
+
+        assert!(config.is_included(&root.join("docs/a.md")));
+        assert!(config.is_included(&root.join("docs/appendix/b.md")));
+        assert!(!config.is_included(&root.join("docs/drafts/c.md")));
+        assert!(!config.is_included(&root.join("d.md")));
+
+        // まだ存在しないファイルも判定できる
+        assert!(config.is_included(&root.join("docs/new.md")));
+
+        let options = config.options_for(&root.join("docs/a.md"), &Rules::default());
+        assert_eq!(80, options.line_length_limit);
+        assert_eq!(LengthUnit::Chars, options.line_length_unit);
+        assert_eq!(["e.g."], options.abbreviations.as_slice());
+        assert_eq!(None, options.max_line_length);
+
+        let options = config.options_for(&root.join("docs/appendix/b.md"), &Rules::default());
+        assert_eq!(120, options.line_length_limit);
+        assert_eq!(Some(150), options.max_line_length);
+
+        // コマンドライン引数が最優先
+        let cli = Rules { line_length_limit: Some(60), ..Rules::default() };
+        let options = config.options_for(&root.join("docs/appendix/b.md"), &cli);
+        assert_eq!(60, options.line_length_limit);
+
+        // リポジトリのルートより上は探索しない（キャッシュの保存先もない）
+        fs::create_dir_all(root.join("nested")).unwrap();
+        fs::write(root.join("nested/.git"), "").unwrap();
+        let config = Config::discover(&root.join("nested")).unwrap();
+        assert_eq!(None, config.root());
+        assert!(config.is_included(&root.join("d.md")));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// `[[overrides]]` のパスの表記の揺れを正規化するテスト
+    #[test]
+    fn test_config_override_path() {
+        let root = std::env::temp_dir().join(format!("sembr-config-path-{}", std::process::id()));
+        fs::create_dir_all(root.join("src/appendix")).unwrap();
+        fs::write(root.join("src/appendix/a.md"), "").unwrap();
+
+        for path in ["src/appendix", "./src/appendix", "src/appendix/", "./src/./appendix/"] {
+            let text = format!("[[overrides]]\npath = '{path}'\nline-length-limit = 120\n");
+            let file = ConfigFile::parse(&text).unwrap();
+            assert_eq!(Path::new("src/appendix"), file.overrides[0].path, "{path}");
+
+            fs::write(root.join(CONFIG_FILE_NAME), &text).unwrap();
+            let config = Config::load(&root.join(CONFIG_FILE_NAME)).unwrap();
+            let options = config.options_for(&root.join("src/appendix/a.md"), &Rules::default());
+            assert_eq!(120, options.line_length_limit, "{path}");
+        }
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 不正な正規表現を読み込み時に検出するテスト
+    #[test]
+    fn test_config_invalid_pattern() {
+        let root =
+            std::env::temp_dir().join(format!("sembr-config-invalid-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let path = root.join(CONFIG_FILE_NAME);
+        fs::write(&path, "never-split-after = ['(']\n").unwrap();
+
+        assert!(matches!(Config::load(&path), Err(Error::InvalidPattern { .. })));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 綴りを誤った項目を読み込み時に検出するテスト
+    #[test]
+    fn test_config_unknown_key() {
+        let valid = "line-length-limit = 80\ninclude = ['src/**']\n\n\
+                     [[overrides]]\npath = 'src/appendix'\nstrip-bom = true\n";
+        assert!(ConfigFile::parse(valid).is_ok());
+
+        let error = ConfigFile::parse("line-lenght-limit = 80\n").unwrap_err();
+        assert!(error.to_string().contains("line-lenght-limit"), "{error}");
+        let error =
+            ConfigFile::parse("[[overrides]]\npath = 'a'\nmax-line-lenght = 80\n").unwrap_err();
+        assert!(error.to_string().contains("max-line-lenght"), "{error}");
+
+        let root =
+            std::env::temp_dir().join(format!("sembr-config-unknown-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let path = root.join(CONFIG_FILE_NAME);
+        fs::write(&path, "line-lenght-limit = 80\n").unwrap();
+
+        assert!(matches!(Config::load(&path), Err(Error::InvalidConfig { .. })));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,209 @@
+//! 整形による変更の行単位の表現
+//!
+//! 整形前後の差分を、置き換える行範囲と置き換え後のテキストの組に分解します。
+//! チェック結果の報告（JSON や SARIF）やエディタの修正案は、これを元に作ります。
+
+use std::collections::HashSet;
+use std::ops::Range;
+
+use imara_diff::{Algorithm, Diff, Hunk, InternedInput};
+use serde::Serialize;
+
+/// 変更が必要な理由
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rule {
+    /// 文の区切りで行を分割する必要がある
+    NeedsSplit,
+    /// 短い行を次の行と結合できる
+    CanJoin,
+}
+
+impl Rule {
+    /// 規則の識別子（`needs-split` または `can-join`）
+    pub fn id(self) -> &'static str {
+        match self {
+            Rule::NeedsSplit => "needs-split",
+            Rule::CanJoin => "can-join",
+        }
+    }
+
+    /// 規則の説明
+    pub fn description(self) -> &'static str {
+        match self {
+            Rule::NeedsSplit => "Put each sentence on its own line.",
+            Rule::CanJoin => "Join short lines that fit within the line length limit.",
+        }
+    }
+}
+
+/// 1つの変更
+///
+/// 行番号は1始まりで、`start_line..=end_line` が整形前のテキストで置き換えられる範囲です。
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Edit {
+    /// 置き換える範囲の最初の行
+    pub start_line: usize,
+    /// 置き換える範囲の最後の行
+    pub end_line: usize,
+    /// 変更が必要な理由
+    pub rule: Rule,
+    /// 置き換え後のテキスト（各行の改行を含む）
+    pub replacement: String,
+}
+
+/// 整形前後のテキストから変更の一覧を求める
+///
+/// 差分の各ハンクを1つの変更とします。
+/// 分割だけを行った結果（`split`）で変更される行をハンクが含んでいれば `needs-split`、
+/// そうでなければ結合による変更なので `can-join` に分類します。
+///
+/// # 引数
+/// - `old`: 整形前のテキスト
+/// - `split`: 分割だけを行ったテキスト
+/// - `new`: 分割と結合を行ったテキスト
+///
+/// # 戻り値
+/// テキスト内での出現順の変更の一覧
+///
+/// # 使用例
+/// ```rust,ignore
+/// let edits = edits("foo. bar\n", "foo.\nbar\n", "foo.\nbar\n");
+/// assert_eq!(edits[0].rule, Rule::NeedsSplit);
+/// ```
+pub(crate) fn edits(old: &str, split: &str, new: &str) -> Vec<Edit> {
+    // 分割によって変更される、整形前の行番号（0始まり）
+    let split_lines: HashSet<u32> =
+        hunks(&InternedInput::new(old, split)).into_iter().flat_map(|hunk| hunk.before).collect();
+
+    let input = InternedInput::new(old, new);
+    hunks(&input)
+        .into_iter()
+        .map(|hunk| {
+            let rule = if hunk.before.clone().any(|line| split_lines.contains(&line)) {
+                Rule::NeedsSplit
+            } else {
+                Rule::CanJoin
+            };
+            let replacement =
+                hunk.after.clone().map(|line| input.interner[input.after[line as usize]]).collect();
+
+            // 整形は既存の行を書き換えるだけなので、挿入のみのハンクは生じないが、
+            // 念のため範囲が空にならないようにする
+            let start_line = hunk.before.start as usize + 1;
+            let end_line = (hunk.before.end as usize).max(start_line);
+
+            Edit { start_line, end_line, rule, replacement }
+        })
+        .collect()
+}
+
+/// 整形前後の差分のうち、選択したハンクだけを適用する
+///
+/// 範囲を指定した整形で、範囲外の段落を元のまま残すために使います。
+///
+/// # 引数
+/// - `old`: 整形前のテキスト（改行で終わる）
+/// - `new`: 整形後のテキスト
+/// - `is_selected`: ハンクが置き換える整形前の行範囲（0始まり、終端は含まない）を受け取り、
+///   そのハンクを適用するかどうかを返す。挿入だけのハンクでは、挿入位置の行を含む範囲を渡す
+///
+/// # 使用例
+/// ```rust,ignore
+/// let new = apply_hunks("a. b\n\nc. d\n", "a.\nb\n\nc.\nd\n", |lines| lines.start == 0);
+/// assert_eq!(new, "a.\nb\n\nc. d\n");
+/// ```
+pub(crate) fn apply_hunks(
+    old: &str,
+    new: &str,
+    is_selected: impl Fn(Range<usize>) -> bool,
+) -> String {
+    let input = InternedInput::new(old, new);
+    let mut output = String::with_capacity(new.len());
+    // 整形前のテキストで、次に出力する行
+    let mut n = 0;
+    for hunk in hunks(&input) {
+        let before = hunk.before.start as usize..hunk.before.end as usize;
+        output.extend(input.before[n..before.start].iter().map(|&line| input.interner[line]));
+
+        let lines = before.start..before.end.max(before.start + 1);
+        if is_selected(lines) {
+            output
+                .extend(hunk.after.clone().map(|line| input.interner[input.after[line as usize]]));
+        } else {
+            output.extend(input.before[before.clone()].iter().map(|&line| input.interner[line]));
+        }
+        n = before.end;
+    }
+    output.extend(input.before[n..].iter().map(|&line| input.interner[line]));
+    output
+}
+
+/// 行単位の差分のハンクを求める
+///
+/// CLI の差分表示と同じく Histogram アルゴリズムを使います。
+fn hunks(input: &InternedInput<&str>) -> Vec<Hunk> {
+    let mut diff = Diff::compute(Algorithm::Histogram, input);
+    diff.postprocess_lines(input);
+    diff.hunks().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Rule, apply_hunks, edits};
+
+    /// 差分のハンクを分割・結合の変更に分類するテスト
+    #[test]
+    fn test_edits() {
+        let old = "\
+# title
+
+first. second
+unchanged
+
+short
+line
+";
+        let split = "\
+# title
+
+first.
+second
+unchanged
+
+short
+line
+";
+        let new = "\
+# title
+
+first.
+second
+unchanged
+
+short line
+";
+        let edits = edits(old, split, new);
+
+        assert_eq!(2, edits.len());
+
+        assert_eq!(Rule::NeedsSplit, edits[0].rule);
+        assert_eq!((3, 3), (edits[0].start_line, edits[0].end_line));
+        assert_eq!("first.\nsecond\n", edits[0].replacement);
+
+        assert_eq!(Rule::CanJoin, edits[1].rule);
+        assert_eq!((6, 7), (edits[1].start_line, edits[1].end_line));
+        assert_eq!("short line\n", edits[1].replacement);
+    }
+
+    /// 選択したハンクだけを適用するテスト
+    #[test]
+    fn test_apply_hunks() {
+        let old = "a. b\n\nc. d\n\ne\nf\n";
+        let new = "a.\nb\n\nc.\nd\n\ne f\n";
+        assert_eq!(new, apply_hunks(old, new, |_| true));
+        assert_eq!(old, apply_hunks(old, new, |_| false));
+        assert_eq!("a. b\n\nc.\nd\n\ne\nf\n", apply_hunks(old, new, |lines| lines.contains(&2)));
+        assert_eq!("a. b\n\nc. d\n\ne f\n", apply_hunks(old, new, |lines| lines.start >= 4));
+    }
+}
@@ -0,0 +1,98 @@
+//! ライブラリのエラー型
+//!
+//! 呼び出し側（CLI、エディタ連携、mdBook プリプロセッサなど）が
+//! 原因ごとに処理を分けられるように、失敗の種類を列挙型で表します。
+
+use std::path::PathBuf;
+use std::{fmt, io};
+
+/// sembr の処理で発生するエラー
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// 設定された正規表現（`never-split-after`）が不正
+    InvalidPattern {
+        /// 設定されたパターン
+        pattern: String,
+        /// コンパイル時のエラー
+        source: regex::Error,
+    },
+
+    /// 設定された glob（`include`、`exclude`）が不正
+    InvalidGlob {
+        /// 設定された glob
+        glob: String,
+        /// コンパイル時のエラー
+        source: globset::Error,
+    },
+
+    /// 設定ファイルが TOML として不正、または未知の値を含む
+    InvalidConfig {
+        /// 設定ファイルのパス
+        path: PathBuf,
+        /// パース時のエラー
+        source: toml::de::Error,
+    },
+
+    /// ファイルの読み書きに失敗
+    Io {
+        /// 対象のパス
+        path: PathBuf,
+        /// 入出力のエラー
+        source: io::Error,
+    },
+
+    /// 整形によってレンダリング結果が変わる
+    ///
+    /// 整形の不具合によって文書が壊れるのを防ぐため、ファイルは書き換えません。
+    SemanticChange {
+        /// 整形前のテキストで、最初に異なるブロックの行（1始まり）
+        line: usize,
+        /// 整形前のブロック
+        old: String,
+        /// 整形後のブロック
+        new: String,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidPattern { pattern, .. } => {
+                write!(f, "invalid never-split-after pattern `{pattern}`")
+            }
+            Error::InvalidGlob { glob, .. } => write!(f, "invalid glob `{glob}`"),
+            Error::InvalidConfig { path, .. } => {
+                write!(f, "invalid configuration file {}", path.display())
+            }
+            Error::Io { path, .. } => write!(f, "{}", path.display()),
+            Error::SemanticChange { line, old, new } => {
+                writeln!(f, "formatting would change the rendered document at line {line}")?;
+                writeln!(f, "before:")?;
+                for line in old.lines() {
+                    writeln!(f, "    {line}")?;
+                }
+                writeln!(f, "after:")?;
+                for line in new.lines() {
+                    writeln!(f, "    {line}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::InvalidPattern { source, .. } => Some(source),
+            Error::InvalidGlob { source, .. } => Some(source),
+            Error::InvalidConfig { source, .. } => Some(source),
+            Error::Io { source, .. } => Some(source),
+            Error::SemanticChange { .. } => None,
+        }
+    }
+}
+
+/// [`Error`] を既定のエラー型とする `Result`
+pub type Result<T, E = Error> = std::result::Result<T, E>;
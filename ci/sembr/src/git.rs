@@ -0,0 +1,330 @@
+//! Git の差分から、変更された行範囲を求める
+//!
+//! `--overwrite` でページ全体を整形すると、変更していない段落まで改行位置が変わり、
+//! `git blame` が汚れたり、翻訳の PR が大きくなったりします。
+//! `--changed-since <REV>` と `--staged` では、差分に重なる段落だけを整形します。
+//!
+//! - `--changed-since <REV>`: 作業ツリーと `<REV>` の差分（`git diff <REV>`）
+//! - `--staged`: インデックスと `HEAD` の差分（`git diff --cached`）。
+//!   ファイルの内容もインデックスから読み込む
+//!
+//! 差分のないファイルと、Git が追跡していないファイルは処理しません。
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::LazyLock;
+
+use anyhow::{Context, Result, bail};
+use regex::Regex;
+
+/// ハンクのヘッダー（`@@ -1,2 +3,4 @@`）から、変更後の開始行と行数を取り出す
+static REGEX_HUNK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^@@ -\d+(?:,\d+)? \+(\d+)(?:,(\d+))? @@").unwrap());
+
+/// 差分の比較対象
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Base {
+    /// 作業ツリーと指定したリビジョンの差分
+    Revision(String),
+    /// インデックス（ステージされた内容）と `HEAD` の差分
+    Index,
+}
+
+/// リポジトリで変更されたファイルと行範囲
+#[derive(Debug)]
+pub struct Changes {
+    /// リポジトリのルート（正規化済み）
+    root: PathBuf,
+    /// 比較対象
+    base: Base,
+    /// ファイルの正規化済みのパスから、変更された行範囲（1始まり、両端を含む）への対応
+    files: HashMap<PathBuf, Vec<RangeInclusive<usize>>>,
+}
+
+impl Changes {
+    /// `path` を含むリポジトリの差分を求める
+    ///
+    /// # 引数
+    /// - `path`: チェック対象のファイルまたはディレクトリ
+    /// - `base`: 差分の比較対象
+    ///
+    /// # 戻り値
+    /// 変更されたファイルと行範囲。
+    /// `git` を実行できない場合や、リポジトリの外の場合はエラー。
+    pub fn load(path: &Path, base: Base) -> Result<Self> {
+        let directory = if path.is_dir() { path } else { path.parent().unwrap_or(path) };
+        let directory = if directory.as_os_str().is_empty() { Path::new(".") } else { directory };
+
+        let root = git(directory, &["rev-parse", "--show-toplevel"])?;
+        let root = fs::canonicalize(root.trim_end())?;
+
+        // 変更後の行範囲だけが必要なので、前後の文脈行は出力しない
+        // パスの接頭辞はユーザーの設定（`diff.noprefix` など）によらず固定する
+        let mut args = vec![
+            "diff",
+            "--unified=0",
+            "--no-color",
+            "--no-ext-diff",
+            "--no-renames",
+            "--src-prefix=a/",
+            "--dst-prefix=b/",
+        ];
+        match &base {
+            Base::Revision(revision) => args.extend([revision.as_str(), "--"]),
+            Base::Index => args.push("--cached"),
+        }
+        let diff = git(&root, &args)?;
+
+        let files =
+            parse_diff(&diff).into_iter().map(|(path, ranges)| (root.join(path), ranges)).collect();
+        Ok(Self { root, base, files })
+    }
+
+    /// ファイルの変更された行範囲
+    ///
+    /// # 戻り値
+    /// 変更がなければ `None`。
+    /// 空白行の削除だけのように、行範囲が空のこともあります。
+    pub fn ranges(&self, path: &Path) -> Option<&[RangeInclusive<usize>]> {
+        let path = fs::canonicalize(path).ok()?;
+        self.files.get(&path).map(Vec::as_slice)
+    }
+
+    /// 差分の比較対象に応じて、ファイルの内容を読み込む
+    ///
+    /// `--staged` ではインデックスから、それ以外では作業ツリーから読み込みます。
+    pub fn read(&self, path: &Path) -> Result<String> {
+        match self.base {
+            Base::Revision(_) => Ok(fs::read_to_string(path)?),
+            Base::Index => {
+                let path = fs::canonicalize(path)?;
+                let relative = path.strip_prefix(&self.root)?;
+                // Git のパスは、Windows でも `/` で区切る
+                let relative = relative.to_string_lossy().replace('\\', "/");
+                git(&self.root, &["show", &format!(":{relative}")])
+            }
+        }
+    }
+}
+
+/// `git` を実行し、標準出力を返す
+///
+/// 非 ASCII 文字（日本語のファイル名など）を含むパスを `\346...` のように
+/// エスケープしないように、`core.quotePath` を無効にします。
+fn git(directory: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(directory)
+        .args(["-c", "core.quotePath=false"])
+        .args(args)
+        .output()
+        .context("failed to run `git`")?;
+    if !output.status.success() {
+        bail!(
+            "`git {}` failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// `git diff --unified=0` の出力から、ファイルごとに変更後の行範囲を求める
+///
+/// # 戻り値
+/// リポジトリのルートからの相対パスから、変更された行範囲（1始まり、両端を含む）への対応。
+/// 削除されたファイルは含みません。
+///
+/// # 処理の詳細
+/// - 行を追加・変更したハンクは、変更後の行範囲
+/// - 行を削除しただけのハンクは、削除位置の前後の行
+///   （削除で段落がつながったり短くなったりするため、その段落も整形する）
+///
+/// # 使用例
+/// ```rust,ignore
+/// let diff = "diff --git a/src/a.md b/src/a.md\n+++ b/src/a.md\n@@ -3 +3,2 @@\n-old\n+new\n+new\n";
+/// assert_eq!(parse_diff(diff)[Path::new("src/a.md")], vec![3..=4]);
+/// ```
+fn parse_diff(diff: &str) -> HashMap<PathBuf, Vec<RangeInclusive<usize>>> {
+    let mut files: HashMap<PathBuf, Vec<RangeInclusive<usize>>> = HashMap::new();
+    // 処理中のファイル（削除されたファイルでは `None`）
+    let mut current: Option<PathBuf> = None;
+    // ファイルのヘッダーの内部（追加された行の `++` をパスと誤認しないため）
+    let mut in_header = false;
+
+    for line in diff.lines() {
+        if line.starts_with("diff --git ") {
+            in_header = true;
+            current = None;
+            continue;
+        }
+        if in_header && let Some(path) = line.strip_prefix("+++ ") {
+            // 空白を含むパスでは、Git は行末にタブを付ける
+            let path = path.strip_suffix('\t').unwrap_or(path);
+            current = unquote(path).strip_prefix("b/").map(PathBuf::from);
+            if let Some(path) = &current {
+                files.entry(path.clone()).or_default();
+            }
+            continue;
+        }
+
+        let (Some(path), Some(captures)) = (&current, REGEX_HUNK.captures(line)) else {
+            continue;
+        };
+        in_header = false;
+        let start: usize = captures[1].parse().unwrap();
+        let count: usize = captures.get(2).map_or(1, |count| count.as_str().parse().unwrap());
+
+        // 行数が0のハンクでは、開始行は削除位置の直前の行を指す
+        let range = match count {
+            0 => start.max(1)..=start + 1,
+            count => start..=start + count - 1,
+        };
+        files.entry(path.clone()).or_default().push(range);
+    }
+
+    files
+}
+
+/// 差分のヘッダーのパスの引用符を外す
+///
+/// 制御文字や `"`、`\\` を含むパスは、`core.quotePath` を無効にしても
+/// C 言語の文字列リテラルの形式で引用されます。
+/// 8進数のエスケープ（`\346`）はバイト列として扱い、UTF-8 として復元します。
+///
+/// # 使用例
+/// ```rust,ignore
+/// assert_eq!(unquote(r#""b/src/\346\227\245.md""#), "b/src/日.md");
+/// assert_eq!(unquote("b/src/a.md"), "b/src/a.md");
+/// ```
+fn unquote(path: &str) -> Cow<'_, str> {
+    let Some(quoted) = path.strip_prefix('"').and_then(|path| path.strip_suffix('"')) else {
+        return Cow::Borrowed(path);
+    };
+
+    let mut bytes = Vec::with_capacity(quoted.len());
+    let mut rest = quoted.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = tail;
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+
+        let Some((&escaped, tail)) = rest.split_first() else {
+            bytes.push(byte);
+            break;
+        };
+        rest = tail;
+        match escaped {
+            b'a' => bytes.push(0x07),
+            b'b' => bytes.push(0x08),
+            b't' => bytes.push(b'\t'),
+            b'n' => bytes.push(b'\n'),
+            b'v' => bytes.push(0x0B),
+            b'f' => bytes.push(0x0C),
+            b'r' => bytes.push(b'\r'),
+            b'0'..=b'7' => {
+                // 最大3桁の8進数
+                let mut value = u32::from(escaped - b'0');
+                for _ in 0..2 {
+                    let Some((&digit @ b'0'..=b'7', tail)) = rest.split_first() else {
+                        break;
+                    };
+                    value = value * 8 + u32::from(digit - b'0');
+                    rest = tail;
+                }
+                bytes.push(value as u8);
+            }
+            // `\"` と `\\` など
+            other => bytes.push(other),
+        }
+    }
+    Cow::Owned(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{parse_diff, unquote};
+
+    /// 差分の解析のテスト
+    #[test]
+    fn test_parse_diff() {
+        let diff = "\
+diff --git a/src/a.md b/src/a.md
+index 0123456..789abcd 100644
+--- a/src/a.md
++++ b/src/a.md
+@@ -3 +3,2 @@ heading
+-old
++new
++new
+@@ -10,2 +11 @@
+-removed
+-changed
++changed
+@@ -20 +19,0 @@
+-removed
+@@ -30,0 +30 @@
++++ added line that looks like a header
+diff --git a/src/b.md b/src/b.md
+deleted file mode 100644
+--- a/src/b.md
++++ /dev/null
+@@ -1 +0,0 @@
+-gone
+diff --git a/src/c.md b/src/c.md
+new file mode 100644
+--- /dev/null
++++ b/src/c.md
+@@ -0,0 +1,3 @@
++a
++b
++c
+";
+        let files = parse_diff(diff);
+        assert_eq!(2, files.len());
+        assert_eq!(vec![3..=4, 11..=11, 19..=20, 30..=30], files[Path::new("src/a.md")]);
+        assert_eq!(vec![1..=3], files[Path::new("src/c.md")]);
+
+        // 冒頭の行の削除は、先頭の行に重なる
+        let files = parse_diff("diff --git a/a.md b/a.md\n+++ b/a.md\n@@ -1 +0,0 @@\n-gone\n");
+        assert_eq!(vec![1..=1], files[Path::new("a.md")]);
+
+        // 引用されたパス（`core.quotePath` が有効な場合の非 ASCII 文字や、`"` を含むパス）
+        let diff = r#"diff --git "a/src/\346\227\245\346\234\254/c.md" "b/src/\346\227\245\346\234\254/c.md"
+--- "a/src/\346\227\245\346\234\254/c.md"
++++ "b/src/\346\227\245\346\234\254/c.md"
+@@ -2 +2 @@
+-old
++new
+diff --git "a/say \"hi\".md" "b/say \"hi\".md"
++++ "b/say \"hi\".md"
+@@ -1 +1 @@
+-old
++new
+"#;
+        let files = parse_diff(diff);
+        assert_eq!(vec![2..=2], files[Path::new("src/日本/c.md")]);
+        assert_eq!(vec![1..=1], files[Path::new(r#"say "hi".md"#)]);
+
+        // 空白を含むパス（Git はヘッダーの行末にタブを付ける）
+        let diff = "diff --git a/foo bar.md b/foo bar.md\n--- a/foo bar.md\t\n+++ b/foo bar.md\t\n@@ -3 +3 @@\n-old\n+new\n";
+        assert_eq!(vec![3..=3], parse_diff(diff)[Path::new("foo bar.md")]);
+    }
+
+    /// パスの引用符を外すテスト
+    #[test]
+    fn test_unquote() {
+        assert_eq!("b/src/a.md", unquote("b/src/a.md"));
+        assert_eq!("b/src/日本.md", unquote(r#""b/src/\346\227\245\346\234\254.md""#));
+        assert_eq!("b/tab\there.md", unquote(r#""b/tab\there.md""#));
+        assert_eq!(r"b/back\slash.md", unquote(r#""b/back\\slash.md""#));
+    }
+}
@@ -0,0 +1,2233 @@
+//! # SEMBR (Sentence Break) - Markdown文章整形ライブラリ
+//!
+//! Markdownファイルの文章を適切に分割・結合し、
+//! 読みやすさとGit差分の品質を向上させます。
+//!
+//! CLI（`sembr` コマンド）のほか、mdBook プリプロセッサやエディタ連携、
+//! date-check などのツールからも同じ規則で整形できるように、
+//! 整形処理をライブラリとして公開しています。
+//!
+//! ## 主な機能
+//! 1. 長い文を句読点（. ! ? ; および全角の 。！？；）で分割
+//! 2. 短い文を結合して行長を最適化
+//! 3. CommonMark の構造に基づき、コードブロックやテーブル、見出しなどを保護
+//!
+//! ## 使用方法
+//! ```rust
+//! use sembr::{Formatter, Options};
+//!
+//! # fn main() -> sembr::Result<()> {
+//! let formatter = Formatter::new(Options::default())?;
+//! let content = "First sentence. Second sentence.\n";
+//!
+//! // 整形後のテキストを求める
+//! let formatted = formatter.format_str(content);
+//! assert_eq!(formatted, "First sentence.\nSecond sentence.\n");
+//!
+//! // 規則に適合させるための行単位の変更を求める
+//! for edit in formatter.check_str(content) {
+//!     println!("{}-{}: {}", edit.start_line, edit.end_line, edit.rule.id());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! 手で整形した段落は `<!-- sembr-ignore -->` などのコメントで整形の対象外にできます。
+//! コメントの誤りは [`Formatter::warnings`] で求められます。
+//!
+//! 整形結果をファイルに書き込む前には、[`verify`] でレンダリング結果が変わらないことを確かめます。
+//!
+//! 改行コード（LF / CRLF）、先頭の BOM、末尾の改行の有無は元のテキストに合わせます。
+//! 統一する場合は [`Options::line_ending`] などで指定します。
+//!
+//! プロジェクトの設定ファイル（`sembr.toml`）の読み込みは [`config`] を参照してください。
+//!
+//! ## クレートの機能（features）
+//! - `cli`（既定で有効）: `sembr` コマンドをビルドし、[`LengthUnit`] などの設定値の列挙型に
+//!   `clap::ValueEnum` を実装します。
+//!   ライブラリだけを使う場合は `default-features = false` で無効にすると、clap に依存しません。
+
+pub mod config;
+mod edit;
+mod error;
+mod line_ending;
+mod markdown;
+mod script;
+mod suppress;
+mod verify;
+mod warning;
+mod width;
+
+use std::ops::{Range, RangeInclusive};
+use std::sync::LazyLock;
+
+pub use edit::{Edit, Rule};
+pub use error::{Error, Result};
+pub use line_ending::LineEnding;
+use line_ending::TextStyle;
+use markdown::Document;
+use regex::Regex;
+pub use script::CjkLatinSpacing;
+use script::join_lines;
+pub use verify::verify;
+pub use warning::{Warning, WarningKind};
+pub use width::LengthUnit;
+use width::line_length;
+
+/// 既定で文末とみなさない略語
+///
+/// これらの略語の直後のピリオドでは文を分割しません。
+/// 大文字・小文字は区別しません（`E.g.` も `e.g.` として扱います）。
+/// 行全体を無視するのではなく、その位置での分割だけを抑制します。
+const DEFAULT_ABBREVIATIONS: &[&str] = &[
+    "e.g.", "i.e.", "etc.", "vs.", "cf.", "et al.", "approx.", "Dr.", "Mr.", "Ms.", "Mrs.", "std.",
+];
+
+/// 既定で分割しない位置の正規表現
+///
+/// 分割位置より前のテキストの末尾がこれにマッチする場合は分割しません。
+/// `r? @reviewer` は文ではなく、レビュー担当者を指定する triagebot のコマンドです。
+const DEFAULT_NEVER_SPLIT_AFTER: &[&str] = &[r"(^|\s)r\?"];
+
+/// 整形処理の設定
+///
+/// コマンドライン引数や設定ファイルのうち、文章の整形規則に関わるものをまとめたものです。
+/// 正規表現などの検証は [`Formatter::new`] で行います。
+///
+/// 設定項目を追加しても互換性を保てるように、構造体リテラルでは作成できません。
+/// [`Options::default`] から必要な項目だけを変更して作成します。
+///
+/// # 使用例
+/// ```rust
+/// use sembr::{LineEnding, Options};
+///
+/// let mut options = Options::default();
+/// options.line_length_limit = 80;
+/// options.line_ending = LineEnding::Lf;
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Options {
+    /// 行を結合する際の最大行長
+    pub line_length_limit: usize,
+
+    /// 行の長さを数える単位
+    pub line_length_unit: LengthUnit,
+
+    /// CJK/ラテン境界で行を結合する際の空白の扱い
+    pub cjk_latin_spacing: CjkLatinSpacing,
+
+    /// 文末とみなさない略語
+    pub abbreviations: Vec<String>,
+
+    /// 追加の文末記号
+    ///
+    /// 全角の文字だけからなる記号は直後で、それ以外は後続の空白の後で分割します。
+    pub extra_terminators: Vec<String>,
+
+    /// 分割位置より前のテキストの末尾にマッチした場合に分割しない正規表現
+    ///
+    /// パターンはテキストの末尾に固定してマッチさせます（`r\?` は `r?` で終わる位置）。
+    pub never_split_after: Vec<String>,
+
+    /// 1行の最大長（`None` なら制限しない）
+    ///
+    /// これを超える文は、節の境界（カンマ、コロン、ダッシュ、接続詞、`、`、`，`）でも分割します。
+    /// 分割しても収まらない行は [`Formatter::warnings`] で報告します。
+    pub max_line_length: Option<usize>,
+
+    /// 整形結果の改行コード（既定では元のテキストに合わせる）
+    pub line_ending: LineEnding,
+
+    /// 末尾に改行のないテキストに改行を追加するかどうか（既定では追加しない）
+    pub insert_final_newline: bool,
+
+    /// 先頭の BOM を取り除くかどうか（既定では残す）
+    pub strip_bom: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            line_length_limit: 100,
+            line_length_unit: LengthUnit::default(),
+            cjk_latin_spacing: CjkLatinSpacing::default(),
+            abbreviations: DEFAULT_ABBREVIATIONS
+                .iter()
+                .map(|&abbreviation| abbreviation.to_owned())
+                .collect(),
+            extra_terminators: Vec::new(),
+            never_split_after: DEFAULT_NEVER_SPLIT_AFTER
+                .iter()
+                .map(|&pattern| pattern.to_owned())
+                .collect(),
+            max_line_length: None,
+            line_ending: LineEnding::default(),
+            insert_final_newline: false,
+            strip_bom: false,
+        }
+    }
+}
+
+/// 文末の句読点パターンにマッチする正規表現
+///
+/// 行末が以下の文字で終わる場合、その行で文が終わっていると判断します：
+/// - `.` (ピリオド)
+/// - `?` (疑問符)
+/// - `;` (セミコロン)
+/// - `!` (感嘆符)
+/// - `,` (カンマ)
+/// - `-` (ハイフン)
+/// - `。！？；、，` (全角の句読点。後ろに閉じ括弧 `」』）】` が続いてもよい)
+///
+/// LazyLock により、初回アクセス時に一度だけコンパイルされます。
+static REGEX_IGNORE_END: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(\.|\?|;|!|,|\-|[。！？；、，][」』）】]*)$").unwrap());
+
+/// 文の分割位置を検出する正規表現
+///
+/// この正規表現は、文を分割すべき位置（句読点の後）を検出します。
+///
+/// # パターンの詳細
+/// - `[^\.\d\-\*]\.`: ドット以外 + ドット（小数点や省略記号を除外）
+/// - `\?`: 疑問符
+/// - `;`: セミコロン
+/// - `!`: 感嘆符
+/// - `\s`: 後続の空白
+/// - `[。！？；][」』）】]*\s*`: 全角の句読点（日本語の文末）
+///
+/// 日本語の文は句読点の後に空白を置かないため、全角の句読点は
+/// 後続の空白を必要としません。
+/// 文を閉じる括弧（`」』）】`）は、それが終える文の側に含めます。
+///
+/// # 除外されるケース
+/// - 小数点（1.5）
+/// - 省略記号（...）
+/// - リスト記号（- や *）
+/// - 引用の直後に「と」が続く場合（`「はい。」と言った`）: [`Formatter::split_sentences`] で除外
+/// - Gitコマンド（r? @reviewer）など: [`Options::never_split_after`] で除外
+///
+/// 設定で追加の文末記号が指定された場合は、[`split_regex`] が
+/// このパターンに選択肢を加えた正規表現を作成します。
+const SPLIT_PATTERN: &str = r"([^\.\d\-\*]\.|\?|;|!)\s|[。！？；][」』）】]*\s*";
+
+/// [`SPLIT_PATTERN`] をコンパイルした正規表現
+static REGEX_SPLIT: LazyLock<Regex> = LazyLock::new(|| Regex::new(SPLIT_PATTERN).unwrap());
+
+/// 追加の文末記号を含めた分割位置の正規表現を作成する
+///
+/// 追加の文末記号がなければ、コンパイル済みの [`REGEX_SPLIT`] を複製して返します。
+///
+/// # 追加の文末記号の扱い
+/// - 全角の文字だけからなる記号: 全角の句読点と同じく、閉じ括弧と空白を含めた直後で分割
+/// - それ以外: 後続の空白が1つ以上ある場合のみ分割（`http://` の `:` などを避けるため）
+fn split_regex(extra_terminators: &[String]) -> Regex {
+    if extra_terminators.is_empty() {
+        return REGEX_SPLIT.clone();
+    }
+
+    let mut pattern = SPLIT_PATTERN.to_owned();
+    for terminator in extra_terminators.iter().filter(|terminator| !terminator.is_empty()) {
+        let escaped = regex::escape(terminator);
+        if terminator
+            .chars()
+            .all(|ch| line_length(ch.encode_utf8(&mut [0; 4]), LengthUnit::Columns) == 2)
+        {
+            pattern.push_str(&format!("|{escaped}[」』）】]*\\s*"));
+        } else {
+            pattern.push_str(&format!("|{escaped}\\s"));
+        }
+    }
+
+    // 文末記号はエスケープ済みなので、コンパイルは失敗しない
+    Regex::new(&pattern).unwrap()
+}
+
+/// リスト項目を検出する正規表現
+///
+/// Markdown のリスト項目（番号付き/記号）を検出します。
+///
+/// # マッチするパターン
+/// - 番号付きリスト: `1. `, `2. `, ...
+/// - ダッシュリスト: `- `
+/// - アスタリスクリスト: `* `
+///
+/// インデントがあっても正しく検出します。
+///
+/// # マッチ例
+/// ```markdown
+/// 1. First item
+///   - Nested item
+///     * More nested
+/// ```
+static REGEX_LIST_ENTRY: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\s*(\d\.|\-|\*)\s+").unwrap());
+
+/// 節の境界を検出する正規表現
+///
+/// 最大行長（[`Options::max_line_length`]）を超える文を分割する位置の候補です。
+///
+/// # パターンの詳細
+/// - `[,:]\s+`: カンマ・コロンと後続の空白（直後で分割）
+/// - `[、，]\s*`: 全角の読点（直後で分割）
+/// - `—\s*`: エムダッシュ（直後で分割）
+/// - `\s(and|but|...)\s`: 接続詞（接続詞の直前で分割）
+static REGEX_CLAUSE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"[,:]\s+|[、，]\s*|—\s*|\s(?:and|but|or|nor|yet|so|because|although|though|while|whereas|which|unless)\s",
+    )
+    .unwrap()
+});
+
+/// 整形（分割と結合）を繰り返す最大回数
+///
+/// 通常は数回で収束します。
+/// 分割と結合が互いに打ち消し合うような入力でも終了するための上限です。
+const MAX_PASSES: usize = 32;
+
+/// 文章の整形器
+///
+/// [`Options`] の正規表現をコンパイルした状態で保持し、
+/// 複数のテキストを同じ規則で整形・チェックします。
+///
+/// # 使用例
+/// ```rust
+/// use sembr::{Formatter, Options, Rule};
+///
+/// # fn main() -> sembr::Result<()> {
+/// let formatter = Formatter::new(Options::default())?;
+/// assert_eq!(formatter.format_str("First. Second.\n"), "First.\nSecond.\n");
+/// assert_eq!(formatter.check_str("First. Second.\n")[0].rule, Rule::NeedsSplit);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Formatter {
+    /// 整形処理の設定
+    options: Options,
+
+    /// 追加の文末記号を含めた分割位置の正規表現（[`split_regex`]）
+    split: Regex,
+
+    /// [`Options::never_split_after`] をコンパイルした正規表現
+    never_split_after: Vec<Regex>,
+}
+
+impl Formatter {
+    /// 設定から整形器を作成する
+    ///
+    /// # エラー
+    /// [`Options::never_split_after`] に不正な正規表現が含まれる場合、
+    /// [`Error::InvalidPattern`] を返します。
+    pub fn new(options: Options) -> Result<Self> {
+        let split = split_regex(&options.extra_terminators);
+        let never_split_after = options
+            .never_split_after
+            .iter()
+            .map(|pattern| never_split_after_regex(pattern))
+            .collect::<Result<_>>()?;
+        Ok(Self { options, split, never_split_after })
+    }
+
+    /// 整形処理の設定
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    /// テキストを整形する
+    ///
+    /// 文の分割と短い行の結合を、結果が変わらなくなるまで繰り返します。
+    /// 結合は1回につき隣り合う2行ずつなので、3行以上の結合には複数回の処理が必要です。
+    /// 結果をもう一度整形しても変わらないため、結果が元のテキストと等しければ、
+    /// そのテキストは規則に適合しています。
+    ///
+    /// 改行コード、BOM、末尾の改行の有無は、設定で統一しない限り元のテキストに合わせます。
+    /// 改行コードを統一しない設定で LF と CRLF が混在していれば、元のテキストをそのまま返します。
+    pub fn format_str(&self, content: &str) -> String {
+        let Some(style) = TextStyle::detect(content, &self.options) else {
+            return content.to_owned();
+        };
+        style.apply(self.format_lines(&line_ending::strip(content)))
+    }
+
+    /// LF で区切られ、BOM のないテキストを整形する（[`Formatter::format_str`] の本体）
+    ///
+    /// 結果は常に改行で終わります。
+    fn format_lines(&self, content: &str) -> String {
+        let mut current = content.to_owned();
+        for _ in 0..MAX_PASSES {
+            let next = self.lengthen_lines(&self.comply(&current));
+            if next == current {
+                break;
+            }
+            current = next;
+        }
+        current
+    }
+
+    /// テキストが規則に適合しているかをチェックする
+    ///
+    /// # 戻り値
+    /// 適合させるための行単位の変更の一覧（適合していれば空）
+    pub fn check_str(&self, content: &str) -> Vec<Edit> {
+        // 置き換え後のテキストも、元のテキストの改行コードに合わせる
+        let Some(style) = TextStyle::detect(content, &self.options) else {
+            return Vec::new();
+        };
+        let stripped = line_ending::strip(content);
+        let split = style.apply(self.comply(&stripped));
+        let new = style.apply(self.format_lines(&stripped));
+        edit::edits(content, &split, &new)
+    }
+
+    /// 整形では解消できない問題を求める
+    ///
+    /// 閉じられていない `<!-- sembr-ignore-start -->` や、
+    /// 対応する開始のない `<!-- sembr-ignore-end -->` を警告します。
+    /// 最大行長（[`Options::max_line_length`]）が指定されていれば、
+    /// 文と節の境界で分割しても収まらない段落の行も警告します。
+    /// 改行コードを統一しない設定で LF と CRLF が混在していれば、整形しないことを警告します。
+    /// 整形結果には影響しないため、[`Formatter::check_str`] とは別に求めます。
+    ///
+    /// # 戻り値
+    /// テキスト内での出現順の警告の一覧
+    pub fn warnings(&self, content: &str) -> Vec<Warning> {
+        let mixed = (self.options.line_ending == LineEnding::Preserve)
+            .then(|| line_ending::mixed_line_endings(content))
+            .flatten();
+        let content = &line_ending::strip(content);
+        let document = Document::parse(content);
+        let mut warnings = document.warnings().to_vec();
+        if let Some(line) = mixed {
+            warnings.push(Warning { line, kind: WarningKind::MixedLineEndings });
+        }
+
+        if let Some(max_line_length) = self.options.max_line_length {
+            let unit = self.options.line_length_unit;
+            for (n, line) in content.lines().enumerate() {
+                if !document.is_prose(n) || line_length(line, unit) <= max_line_length {
+                    continue;
+                }
+
+                // 分割後の2行目以降には、継続行の接頭辞が付く
+                let prefix = line_length(&line[..document.prefix(n)], unit);
+                let portions =
+                    self.split_line(line, document.prefix(n), |offset| document.is_text(n, offset));
+                let too_long = portions.iter().enumerate().any(|(index, portion)| {
+                    let extra = if index == 0 { 0 } else { prefix };
+                    extra + line_length(portion.trim_end(), unit) > max_line_length
+                });
+                if too_long {
+                    warnings.push(Warning { line: n + 1, kind: WarningKind::LineTooLong });
+                }
+            }
+        }
+
+        warnings.sort_by_key(|warning| warning.line);
+        warnings
+    }
+
+    /// 指定した行範囲に重なる段落だけを整形する
+    ///
+    /// エディタで編集中の段落だけを整形する（rustfmt の `--file-lines` に相当）ために使います。
+    /// 範囲外の段落や、段落以外の行は元のまま残します。
+    ///
+    /// # 引数
+    /// - `content`: 処理対象のMarkdownテキスト
+    /// - `lines`: 整形する行範囲（1始まり、両端を含む）
+    ///
+    /// # 使用例
+    /// ```rust
+    /// # use sembr::{Formatter, Options};
+    /// # let formatter = Formatter::new(Options::default()).unwrap();
+    /// let text = "First. Second.\n\nThird. Fourth.\n";
+    /// assert_eq!(formatter.format_range(text, 3..=3), "First. Second.\n\nThird.\nFourth.\n");
+    /// ```
+    pub fn format_range(&self, content: &str, lines: RangeInclusive<usize>) -> String {
+        self.format_ranges(content, &[lines])
+    }
+
+    /// 指定した複数の行範囲のいずれかに重なる段落だけを整形する
+    ///
+    /// Git の差分の各ハンクのように、離れた複数の範囲を一度に整形するために使います。
+    /// 範囲が空であれば、どの段落も整形しません。
+    ///
+    /// # 引数
+    /// - `content`: 処理対象のMarkdownテキスト
+    /// - `ranges`: 整形する行範囲の一覧（1始まり、両端を含む）
+    ///
+    /// # 処理の詳細
+    /// 1. テキスト全体を整形
+    /// 2. 整形前の文書から、いずれかの範囲に重なる段落の行範囲を求める
+    /// 3. 整形前後の行単位の差分を求める
+    /// 4. 範囲または範囲に重なる段落に重なるハンクは整形後の行、それ以外は整形前の行を出力
+    ///
+    /// 整形は段落の内側の行だけを書き換えるため、差分のハンクから元の段落がわかります。
+    /// 整形前後の段落の一覧を対応付ける必要はありません。
+    ///
+    /// # 使用例
+    /// ```rust
+    /// # use sembr::{Formatter, Options};
+    /// # let formatter = Formatter::new(Options::default()).unwrap();
+    /// let text = "First. Second.\n\nThird. Fourth.\n\nFifth. Sixth.\n";
+    /// assert_eq!(
+    ///     formatter.format_ranges(text, &[1..=1, 5..=5]),
+    ///     "First.\nSecond.\n\nThird. Fourth.\n\nFifth.\nSixth.\n",
+    /// );
+    /// ```
+    pub fn format_ranges(&self, content: &str, ranges: &[RangeInclusive<usize>]) -> String {
+        let Some(style) = TextStyle::detect(content, &self.options) else {
+            return content.to_owned();
+        };
+        // 整形結果と同じく改行で終わるテキストにして、差分を求める
+        let mut content = line_ending::strip(content).into_owned();
+        if !content.ends_with('\n') {
+            content.push('\n');
+        }
+        let new = self.format_lines(&content);
+
+        // 1始まりの範囲を0始まりに変換し、範囲に重なる段落全体に広げる
+        let mut selected: Vec<_> =
+            ranges.iter().map(|lines| lines.start().saturating_sub(1)..*lines.end()).collect();
+        let paragraphs: Vec<_> = Document::parse(&content)
+            .paragraphs()
+            .into_iter()
+            .filter(|paragraph| selected.iter().any(|lines| overlaps(lines, paragraph)))
+            .collect();
+        selected.extend(paragraphs);
+
+        let result =
+            edit::apply_hunks(&content, &new, |lines| selected.iter().any(|s| overlaps(s, &lines)));
+
+        // 元のテキストの形式に戻す
+        style.apply(result)
+    }
+
+    /// 文章を規則に適合させる（文を分割する）
+    ///
+    /// 長い文を句読点の位置で適切に分割します。
+    /// これにより、Git差分が見やすくなり、レビューが容易になります。
+    ///
+    /// # 引数
+    /// - `content`: 処理対象のMarkdownテキスト
+    ///
+    /// # 戻り値
+    /// 分割後のテキスト
+    ///
+    /// # 処理の詳細
+    /// 1. 文書を CommonMark として解析（[`Document`]）
+    /// 2. 各行を走査
+    /// 3. 段落の文章を含まない行（コードブロック、テーブルなど）はそのまま出力
+    /// 4. 句読点パターンにマッチし、かつ段落のテキストノード内にある位置で分割
+    /// 5. 分割後の2つ目以降の部分に、継続行の接頭辞を付与して出力
+    ///
+    /// 行の一覧は元のテキストを借用し、結果は1つの出力バッファに順に書き込みます。
+    /// 行の挿入や削除がないため、処理時間はテキストの長さに比例します。
+    ///
+    /// # 接頭辞の処理
+    /// 行頭のコンテナの接頭辞（[`Document::prefix`]）から継続行の接頭辞を求めます
+    /// （[`markdown::continuation`]）。
+    /// - 引用ブロックの場合: `>` をそのまま残す（例: `> ` → `> `）
+    /// - リスト項目の場合: リストマーカーを同じ幅の空白に置き換える（例: `> 1. ` → `>    `）
+    /// - 通常の行の場合: 行頭の空白をそのまま使う
+    ///
+    /// # 使用例
+    /// ```rust,ignore
+    /// let text = "First sentence. Second sentence.\n";
+    /// let result = formatter.comply(text);
+    /// // "First sentence.\nSecond sentence.\n"
+    /// ```
+    fn comply(&self, content: &str) -> String {
+        // 文書の構造を解析
+        let document = Document::parse(content);
+
+        // 前後の行を参照するため、行の一覧を作る（元のテキストを借用し、複製しない）
+        let lines: Vec<&str> = content.lines().collect();
+
+        // 出力先（分割で増える改行と接頭辞の分だけ余裕を持たせる）
+        let mut output = String::with_capacity(content.len() + content.len() / 8);
+
+        // 各行を処理し、分割後の行を順に出力する
+        for (n, &line) in lines.iter().enumerate() {
+            // 分割しない行はそのまま出力
+            if !self.can_split(&document, &lines, n) {
+                push_line(&mut output, line);
+                continue;
+            }
+
+            // 段落のテキストノード内にある文の境界で分割（区切り文字を含む）
+            // インラインコードや HTML タグの内側、略語の直後では分割しない
+            // 最大行長を超える文は、さらに節の境界で分割する
+            let portions =
+                self.split_line(line, document.prefix(n), |offset| document.is_text(n, offset));
+
+            // 継続行の接頭辞を計算
+            // 例: "1. " → "   "
+            // 例: "> - " → ">   "
+            let prefix = markdown::continuation(&line[..document.prefix(n)]);
+
+            // 2つ目以降の部分に接頭辞を付け、最後の部分以外の末尾の空白を削除
+            // 最後の部分の末尾の空白は、ハード改行（行末の2つの空白）の場合があるので残す
+            let last = portions.len() - 1;
+            for (index, portion) in portions.into_iter().enumerate() {
+                if index != 0 {
+                    output.push_str(&prefix);
+                }
+                push_line(&mut output, if index == last { portion } else { portion.trim_end() });
+            }
+        }
+
+        finish(output)
+    }
+
+    /// 行を分割の対象にするかどうか
+    ///
+    /// # 分割しない行
+    /// - 段落の文章を含まない行（コードブロック、テーブルなど）
+    /// - `|` を含み、次の行がテーブルの区切り行に見える行。
+    ///   分割後の最後の部分がヘッダー行になり、段落の途中でテーブルが始まることがある
+    fn can_split(&self, document: &Document, lines: &[&str], n: usize) -> bool {
+        document.is_prose(n)
+            && !(lines[n].contains('|')
+                && lines.get(n + 1).is_some_and(|next_line| {
+                    markdown::is_table_delimiter(&next_line[document.prefix(n + 1)..])
+                }))
+    }
+
+    /// 行を文の境界で分割する
+    ///
+    /// 分割位置の正規表現（[`split_regex`]）にマッチした位置の直後で行を区切ります。
+    /// 区切り文字と後続の空白は、前の部分に含まれます
+    /// （`str::split_inclusive` と同じ挙動）。
+    ///
+    /// # 分割しない位置
+    /// - 行末（後ろに続く文がない）
+    /// - 全角の閉じ括弧の直後に「と」が続く位置（`「はい。」と言った` のような引用）
+    /// - 略語（`e.g.` など）の直後
+    /// - 直前のテキストが [`Options::never_split_after`] にマッチする位置（`r? @reviewer` など）
+    /// - 分割後の行頭がコードフェンスや見出しなどのブロック構造になる位置（[`markdown::starts_block`]）
+    /// - 行頭から分割位置までがリンク参照定義になる位置（[`markdown::is_link_definition`]）
+    /// - 分割前の部分だけでブロック構造になる位置（情報文字列を含むコードフェンスなど）
+    /// - 区切りの最後の文字が `is_text` を満たさない位置
+    ///   （インラインコード、リンク、強調、自動リンク、インライン HTML の内側）
+    ///
+    /// # 引数
+    /// - `line`: 分割対象の行
+    /// - `is_text`: 行内のバイト位置が段落のテキストノード内にあるかどうか
+    ///
+    /// # 戻り値
+    /// 分割後の各部分（少なくとも1要素）
+    ///
+    /// # 使用例
+    /// ```rust,ignore
+    /// assert_eq!(formatter.split_sentences("一文目。二文目。", |_| true), ["一文目。", "二文目。"]);
+    /// assert_eq!(formatter.split_sentences("foo. bar", |_| true), ["foo. ", "bar"]);
+    /// ```
+    fn split_sentences<'a>(&self, line: &'a str, is_text: impl Fn(usize) -> bool) -> Vec<&'a str> {
+        let mut portions = Vec::new();
+
+        // 現在の部分の開始位置
+        let mut start = 0;
+
+        for regex_match in self.split.find_iter(line) {
+            let end = regex_match.end();
+            let rest = &line[end..];
+
+            // 行末では分割しない
+            if rest.is_empty() {
+                continue;
+            }
+
+            // 区切りがテキストノードの外側（インラインコードなど）にあれば分割しない
+            if !is_text(end - 1) {
+                continue;
+            }
+
+            // 略語の直後のピリオドは文末ではない
+            if ends_with_abbreviation(&line[..end], &self.options.abbreviations) {
+                continue;
+            }
+
+            // 設定で分割しないと指定された位置（`r?` など）
+            let before = line[..end].trim_end();
+            if self.never_split_after.iter().any(|regex| regex.is_match(before)) {
+                continue;
+            }
+
+            // 分割後の行頭がコードフェンスや見出しなどになる位置、
+            // 分割前の部分がリンク参照定義になる位置では分割しない
+            if markdown::starts_block(rest) || markdown::is_link_definition(&line[..end]) {
+                continue;
+            }
+
+            // 分割前の部分だけで行がブロック構造になる位置（`` ``` why? `code` `` など）では分割しない
+            if markdown::starts_block(&line[start..end]) && !markdown::starts_block(&line[start..])
+            {
+                continue;
+            }
+
+            // 引用の閉じ括弧の直後に「と」が続く場合は文の途中
+            if regex_match.as_str().trim_end().ends_with(['」', '』']) && rest.starts_with('と')
+            {
+                continue;
+            }
+
+            portions.push(&line[start..end]);
+            start = end;
+        }
+
+        portions.push(&line[start..]);
+        portions
+    }
+
+    /// 行を文の境界で分割し、最大行長を超える文をさらに節の境界で分割する
+    ///
+    /// 最大行長（[`Options::max_line_length`]）が指定されていなければ、
+    /// [`Formatter::split_sentences`] と同じです。
+    ///
+    /// # 引数
+    /// - `line`: 分割対象の行
+    /// - `prefix`: 行頭のコンテナの接頭辞のバイト数
+    /// - `is_text`: 行内のバイト位置が段落のテキストノード内にあるかどうか
+    ///
+    /// # 戻り値
+    /// 分割後の各部分（少なくとも1要素）
+    fn split_line<'a>(
+        &self,
+        line: &'a str,
+        prefix: usize,
+        is_text: impl Fn(usize) -> bool,
+    ) -> Vec<&'a str> {
+        let sentences = self.split_sentences(line, &is_text);
+        let Some(max_line_length) = self.options.max_line_length else {
+            return sentences;
+        };
+
+        // 各文の行内での範囲を求め、節の境界で分割
+        let mut portions = Vec::new();
+        let mut start = 0;
+        for sentence in sentences {
+            let end = start + sentence.len();
+            portions.extend(self.split_clauses(
+                line,
+                start..end,
+                prefix,
+                max_line_length,
+                &is_text,
+            ));
+            start = end;
+        }
+        portions
+    }
+
+    /// 最大行長を超える文を節の境界で分割する
+    ///
+    /// 各行が最大行長に収まる範囲で、できるだけ後ろの節の境界を選びます。
+    /// 収まる境界がなければ最初の境界で分割し、境界がなければそのまま残します
+    /// （残った長い行は [`Formatter::warnings`] で報告します）。
+    ///
+    /// # 分割しない位置
+    /// - 区切りがテキストノードの外側（インラインコード、リンクなど）にある位置
+    /// - 分割後の行頭がブロック構造になる位置（[`markdown::starts_block`]）
+    /// - 分割で区切られた部分が空白だけになる位置や、ブロック構造になる位置
+    /// - 行頭から分割位置までがリンク参照定義になる位置（[`markdown::is_link_definition`]）
+    /// - 行頭のコンテナの接頭辞の内側
+    ///
+    /// # 引数
+    /// - `line`: 文を含む行
+    /// - `sentence`: 行内での文の範囲
+    /// - `prefix`: 行頭のコンテナの接頭辞のバイト数
+    /// - `max_line_length`: 1行の最大長
+    /// - `is_text`: 行内のバイト位置が段落のテキストノード内にあるかどうか
+    ///
+    /// # 使用例
+    /// ```rust,ignore
+    /// let portions = formatter.split_clauses("aaa, bbb", 0..8, 0, 5, |_| true);
+    /// assert_eq!(portions, ["aaa, ", "bbb"]);
+    /// ```
+    fn split_clauses<'a>(
+        &self,
+        line: &'a str,
+        sentence: Range<usize>,
+        prefix: usize,
+        max_line_length: usize,
+        is_text: impl Fn(usize) -> bool,
+    ) -> Vec<&'a str> {
+        let unit = self.options.line_length_unit;
+        let prefix_length = line_length(&line[..prefix], unit);
+
+        // 行の一部分を1行にしたときの長さ（2行目以降は継続行の接頭辞を含む）
+        let length = |range: Range<usize>| {
+            let extra = if range.start == 0 { 0 } else { prefix_length };
+            extra + line_length(line[range].trim_end(), unit)
+        };
+
+        // 分割位置の候補（新しい行が始まるバイト位置）
+        let candidates: Vec<usize> = REGEX_CLAUSE
+            .find_iter(&line[sentence.clone()])
+            .filter_map(|regex_match| {
+                let start = sentence.start + regex_match.start();
+                let end = sentence.start + regex_match.end();
+                if regex_match.as_str().starts_with(char::is_whitespace) {
+                    // 接続詞の直前で分割
+                    let word = start + regex_match.as_str().find(|ch: char| !ch.is_whitespace())?;
+                    is_text(word).then_some(word)
+                } else {
+                    // 区切り文字の直後で分割
+                    is_text(start).then_some(end)
+                }
+            })
+            .filter(|&position| {
+                prefix < position
+                    && position < sentence.end
+                    && !markdown::starts_block(&line[position..sentence.end])
+                    && !markdown::is_link_definition(&line[..position])
+            })
+            .collect();
+
+        let mut portions = Vec::new();
+        let mut start = sentence.start;
+        while length(start..sentence.end) > max_line_length {
+            // 空白だけの部分（空行になり段落が分かれる）や、
+            // ブロック構造になる部分（`***` だけの行など）ができる位置は除く
+            let mut rest = candidates.iter().copied().filter(|&position| {
+                start < position && {
+                    let portion = &line[start.max(prefix)..position];
+                    !portion.trim().is_empty() && !markdown::starts_block(portion)
+                }
+            });
+            let Some(first) = rest.next() else {
+                break;
+            };
+            let end = std::iter::once(first)
+                .chain(rest)
+                .take_while(|&position| length(start..position) <= max_line_length)
+                .last()
+                .unwrap_or(first);
+            portions.push(&line[start..end]);
+            start = end;
+        }
+        portions.push(&line[start..sentence.end]);
+        portions
+    }
+
+    /// 短い行を結合する
+    ///
+    /// 分割された短い行を結合して、行長を最適化します。
+    /// これにより、読みやすさを保ちながらファイルサイズを削減できます。
+    ///
+    /// # 引数
+    /// - `content`: 処理対象のテキスト
+    ///
+    /// 設定のうち、次の項目を使います。
+    /// - `line_length_limit`: 行長の上限（これ以下なら結合を試みる）
+    /// - `line_length_unit`: 行長の単位（表示幅または文字数）
+    /// - `cjk_latin_spacing`: CJK/ラテン境界での空白の扱い
+    ///
+    /// # 戻り値
+    /// 結合後のテキスト
+    ///
+    /// # 結合しない条件
+    /// - 結合後の行が最大行長（[`Options::max_line_length`]）を超える
+    /// - 行末が段落内のソフト改行でない（コードブロック、見出し、ハード改行、段落の終わりなど）
+    /// - HTML 要素（`<div>` や `<details>` など）の内側
+    /// - 分割位置を含む行
+    /// - 次の行がリスト項目
+    /// - 現在の行が句読点で終わる（略語で終わる場合を除く）
+    ///
+    /// # 処理の詳細
+    /// 1. 各行をチェック
+    /// 2. 結合可能かを判定（[`Formatter::join_next`]）
+    /// 3. 可能なら次の行と結合して出力し（区切りの空白は文字種で決定、[`join_lines`] を参照）、
+    ///    次の行を読み飛ばす
+    /// 4. 結合できなければ元の行を出力
+    ///
+    /// # 使用例
+    /// ```rust,ignore
+    /// let text = "Short line.\nAnother short line.\n";
+    /// let result = formatter.lengthen_lines(text);
+    /// // "Short line. Another short line.\n"
+    /// ```
+    fn lengthen_lines(&self, content: &str) -> String {
+        // 文書の構造を解析
+        let document = Document::parse(content);
+
+        // 前後の行を参照するため、行の一覧を作る（元のテキストを借用し、複製しない）
+        let lines: Vec<&str> = content.lines().collect();
+
+        // 出力先（結合で行は短くなるので、元の長さで足りる）
+        let mut output = String::with_capacity(content.len());
+
+        // 各行を処理し、結合した行または元の行を順に出力する
+        let mut n = 0;
+        while n < lines.len() {
+            match self.join_next(&document, &lines, n) {
+                Some(joined) => {
+                    push_line(&mut output, &joined);
+                    // 結合した次の行はスキップ
+                    n += 2;
+                }
+                None => {
+                    push_line(&mut output, lines[n]);
+                    n += 1;
+                }
+            }
+        }
+
+        finish(output)
+    }
+
+    /// 行を次の行と結合できれば、結合後の行を返す
+    ///
+    /// 結合しない条件は [`Formatter::lengthen_lines`] を参照してください。
+    fn join_next(&self, document: &Document, lines: &[&str], n: usize) -> Option<String> {
+        let line = lines[n];
+
+        // 行末がソフト改行でない行（段落の終わり、コードブロック、HTML 要素内など）はスキップ
+        if !document.can_join(n) {
+            return None;
+        }
+
+        // 分割位置を含む行はスキップ
+        if self.split_sentences(line, |offset| document.is_text(n, offset)).len() > 1 {
+            return None;
+        }
+
+        // 次の行を取得し、コンテナの接頭辞（`> ` や継続行のインデント）を取り除く
+        // 次の行がなければ結合しない
+        let next_line = &lines.get(n + 1)?[document.prefix(n + 1)..];
+
+        // 次の行が結合不可能な条件をチェック
+        // 略語で終わる行（`e.g.` など）は文の途中なので結合してよい
+        if REGEX_LIST_ENTRY.is_match(next_line)
+            || (REGEX_IGNORE_END.is_match(line)
+                && !ends_with_abbreviation(line, &self.options.abbreviations))
+        {
+            return None;
+        }
+
+        // 結合後の長さをチェック
+        // バイト数ではなく表示幅（または文字数）で比較する
+        let length = line_length(line, self.options.line_length_unit)
+            + line_length(next_line, self.options.line_length_unit);
+        if length >= self.options.line_length_limit {
+            return None;
+        }
+
+        // CJK文字同士なら空白なし、ラテン文字同士なら空白を挿入
+        let joined = join_lines(line, next_line, self.options.cjk_latin_spacing);
+
+        // 最大行長を超える場合は、節の境界で分割した行を元に戻さない
+        let fits = self.options.max_line_length.is_none_or(|max_line_length| {
+            line_length(&joined, self.options.line_length_unit) <= max_line_length
+        });
+
+        // 結合後の行頭がリスト項目などになる場合（`*` だけの行など）は結合しない
+        let starts_block = markdown::starts_block(&joined[document.prefix(n)..]);
+
+        // 結合後の行がテーブルの区切り行になる場合や、次の区切り行と列数が揃う場合は、
+        // 段落の途中でテーブルが始まることがあるので結合しない
+        let starts_table = joined.contains('|')
+            && (markdown::is_table_delimiter(&joined[document.prefix(n)..])
+                || lines.get(n + 2).is_some_and(|line| {
+                    markdown::is_table_delimiter(&line[document.prefix(n + 2)..])
+                }));
+
+        (fits && !starts_block && !starts_table).then_some(joined)
+    }
+}
+
+/// 2つの行範囲（終端は含まない）が重なるかどうか
+fn overlaps(a: &Range<usize>, b: &Range<usize>) -> bool {
+    a.start < b.end && b.start < a.end
+}
+
+/// 出力に1行を追加する（末尾に改行を付ける）
+fn push_line(output: &mut String, line: &str) {
+    output.push_str(line);
+    output.push('\n');
+}
+
+/// 出力を仕上げる
+///
+/// 空のテキストは、1行の空行として出力します（`lines().join("\n") + "\n"` と同じ結果）。
+/// 元のテキストの末尾の改行の有無は、[`TextStyle::apply`] で戻します。
+fn finish(mut output: String) -> String {
+    if output.is_empty() {
+        output.push('\n');
+    }
+    output
+}
+
+/// テキストが略語で終わっているかどうかを判定
+///
+/// 末尾の空白を除いたテキストが、いずれかの略語で終わっていれば `true` を返します。
+/// 略語の直前が英数字の場合（`size.` に対する `e.`）は略語とみなしません。
+/// 大文字・小文字は区別しません。
+///
+/// # 引数
+/// - `text`: 判定対象のテキスト（分割位置より前の部分）
+/// - `abbreviations`: 略語のリスト
+///
+/// # 使用例
+/// ```rust,ignore
+/// let abbreviations = Options::default().abbreviations;
+/// assert!(ends_with_abbreviation("see E.g. ", &abbreviations));
+/// assert!(!ends_with_abbreviation("This is a normal sentence.", &abbreviations));
+/// ```
+fn ends_with_abbreviation(text: &str, abbreviations: &[String]) -> bool {
+    let text = text.trim_end();
+    abbreviations.iter().any(|abbreviation| {
+        let Some(start) = text.len().checked_sub(abbreviation.len()) else {
+            return false;
+        };
+        text.is_char_boundary(start)
+            && text[start..].eq_ignore_ascii_case(abbreviation)
+            // 単語の途中から始まる場合は略語ではない
+            && !text[..start].chars().next_back().is_some_and(char::is_alphanumeric)
+    })
+}
+
+/// `never-split-after` のパターンを、テキストの末尾にマッチする正規表現にコンパイルする
+///
+/// # 使用例
+/// ```rust,ignore
+/// let regex = never_split_after_regex(r"(^|\s)r\?")?;
+/// assert!(regex.is_match("r?"));
+/// assert!(!regex.is_match("r? @reviewer"));
+/// ```
+fn never_split_after_regex(pattern: &str) -> Result<Regex> {
+    Regex::new(&format!("(?:{pattern})$"))
+        .map_err(|source| Error::InvalidPattern { pattern: pattern.to_owned(), source })
+}
+
+// テストモジュール
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use proptest::prelude::*;
+    use pulldown_cmark::{Event, Parser};
+
+    use super::{Error, Formatter, LineEnding, Options, Rule, WarningKind, verify};
+    use crate::markdown;
+    use crate::script::CjkLatinSpacing;
+    use crate::width::LengthUnit;
+
+    /// 指定した行長制限を持つ設定を作成する
+    fn options(line_length_limit: usize) -> Options {
+        Options { line_length_limit, ..Options::default() }
+    }
+
+    /// 設定を使って文を分割する
+    fn comply(content: &str, options: &Options) -> String {
+        Formatter::new(options.clone()).unwrap().comply(content)
+    }
+
+    /// 設定を使って短い行を結合する
+    fn lengthen_lines(content: &str, options: &Options) -> String {
+        Formatter::new(options.clone()).unwrap().lengthen_lines(content)
+    }
+
+    /// 文書のブロック構造（要素の開始と終了の並び）を求める
+    ///
+    /// 整形の前後でこれが一致すれば、レンダリングされる構造は変わっていません。
+    fn structure(content: &str) -> Vec<String> {
+        Parser::new_ext(content, markdown::parser_options())
+            .filter(|event| matches!(event, Event::Start(_) | Event::End(_) | Event::Rule))
+            .map(|event| format!("{event:?}"))
+            .collect()
+    }
+
+    /// sembr（文の分割）機能のテスト
+    ///
+    /// 様々な形式の文を正しく分割できることを検証します。
+    #[test]
+    fn test_sembr() {
+        // テスト用の入力テキスト
+        let original = "
+# some. heading
+must! be; split?
+1. ignore a dot after number. but no further
+ignore | tables
+ignore e.g. and
+ignore i.e. and
+ignore E.g. too
+- list. entry
+ * list. entry
+```
+some code. block
+```
+sentence with *italics* should not be ignored. truly.
+git log main.. compiler
+ foo.   bar.  baz
+";
+
+        // 期待される出力
+        let expected = "
+# some. heading
+must!
+be;
+split?
+1. ignore a dot after number.
+   but no further
+ignore | tables
+ignore e.g. and
+ignore i.e. and
+ignore E.g. too
+- list.
+  entry
+ * list.
+   entry
+```
+some code. block
+```
+sentence with *italics* should not be ignored.
+truly.
+git log main.. compiler
+ foo.
+   bar.
+  baz
+";
+
+        // comply 関数をテスト
+        assert_eq!(expected, comply(original, &Options::default()));
+    }
+
+    /// prettify（文の結合）機能のテスト
+    ///
+    /// 短い文を適切に結合し、特殊な構造（リスト、div）を
+    /// 保護できることを検証します。
+    #[test]
+    fn test_prettify() {
+        let original = "\
+do not split
+short sentences
+<div class='warning'>
+a bit of text inside
+</div>
+preserve next line
+1. one
+
+preserve next line
+- two
+
+preserve next line
+* three
+";
+
+        let expected = "\
+do not split short sentences
+<div class='warning'>
+a bit of text inside
+</div>
+preserve next line
+1. one
+
+preserve next line
+- two
+
+preserve next line
+* three
+";
+
+        assert_eq!(expected, lengthen_lines(original, &options(50)));
+    }
+
+    /// インデント付き行の結合テスト
+    ///
+    /// インデントがある行も正しく結合できることを検証します。
+    #[test]
+    fn test_prettify_prefix_spaces() {
+        let original = "\
+ do not split
+ short sentences
+";
+
+        let expected = "\
+ do not split short sentences
+";
+
+        assert_eq!(expected, lengthen_lines(original, &options(50)));
+    }
+
+    /// リンクターゲットの保護テスト
+    ///
+    /// リンクターゲット定義が結合されないことを検証します。
+    #[test]
+    fn test_prettify_ignore_link_targets() {
+        let original = "\
+[a target]: https://example.com
+[another target]: https://example.com
+";
+
+        // リンクターゲットは結合されないため、変更なし
+        assert_eq!(original, lengthen_lines(original, &options(100)));
+    }
+
+    /// インライン要素を壊さずに結合するテスト
+    ///
+    /// 複数行にまたがるインラインコードや HTML タグは結合されず、
+    /// リンクテキストや強調の途中の改行は通常の文章と同様に結合されることを検証します。
+    #[test]
+    fn test_prettify_inline_atomic() {
+        let original = "\
+code `spans
+across` lines
+
+html <span
+class=\"x\">tags</span>
+
+[link
+text](url)
+
+*emphasis
+text*
+
+[リンク
+テキスト](url)
+";
+
+        let expected = "\
+code `spans
+across` lines
+
+html <span
+class=\"x\">tags</span>
+
+[link text](url)
+
+*emphasis text*
+
+[リンクテキスト](url)
+";
+
+        assert_eq!(expected, lengthen_lines(original, &options(50)));
+    }
+
+    /// 日本語の文の結合テスト
+    ///
+    /// `test_prettify` と同じ構造の日本語の入力に対して、
+    /// CJK文字の間に空白を挿入せずに結合できることを検証します。
+    #[test]
+    fn test_prettify_cjk() {
+        let original = "\
+分割しない
+短い文
+<div class='warning'>
+少しの
+テキスト
+</div>
+次の行を保持
+1. 一つ目
+
+次の行を保持
+- 二つ目
+
+次の行を保持
+* 三つ目
+";
+
+        let expected = "\
+分割しない短い文
+<div class='warning'>
+少しの
+テキスト
+</div>
+次の行を保持
+1. 一つ目
+
+次の行を保持
+- 二つ目
+
+次の行を保持
+* 三つ目
+";
+
+        assert_eq!(expected, lengthen_lines(original, &options(50)));
+    }
+
+    /// インデント付きの日本語の行の結合テスト
+    #[test]
+    fn test_prettify_cjk_prefix_spaces() {
+        let original = "\
+ 分割しない
+ 短い文
+";
+
+        let expected = "\
+ 分割しない短い文
+";
+
+        assert_eq!(expected, lengthen_lines(original, &options(50)));
+    }
+
+    /// CJK/ラテン境界の結合テスト
+    ///
+    /// 境界での空白の扱いが設定に従うことを検証します。
+    #[test]
+    fn test_prettify_cjk_latin_boundary() {
+        let original = "\
+`rustc`の
+compiler
+";
+
+        let expected = "\
+`rustc`のcompiler
+";
+        assert_eq!(expected, lengthen_lines(original, &options(50)));
+
+        let expected = "\
+`rustc`の compiler
+";
+        let options = Options { cjk_latin_spacing: CjkLatinSpacing::Space, ..options(50) };
+        assert_eq!(expected, lengthen_lines(original, &options));
+    }
+
+    /// 行長を表示幅で数えるテスト
+    ///
+    /// バイト数では制限を超える日本語の行も、表示幅や文字数で
+    /// 制限内であれば結合されることを検証します。
+    #[test]
+    fn test_prettify_line_length_unit() {
+        // 表示幅 14 + 14 = 28、文字数 7 + 7 = 14、バイト数 21 + 21 = 42
+        let original = "\
+これは短い文の
+前半と後半です
+";
+        let joined = "\
+これは短い文の前半と後半です
+";
+
+        // 表示幅では制限 30 に収まる
+        assert_eq!(joined, lengthen_lines(original, &options(30)));
+
+        // 表示幅では制限 20 を超える
+        assert_eq!(original, lengthen_lines(original, &options(20)));
+
+        // 文字数では制限 20 に収まる
+        let options = Options { line_length_unit: LengthUnit::Chars, ..options(20) };
+        assert_eq!(joined, lengthen_lines(original, &options));
+    }
+
+    /// 分割と結合の統合テスト
+    ///
+    /// comply と lengthen_lines を連続して適用した場合の
+    /// 動作を検証します。
+    #[test]
+    fn test_sembr_then_prettify() {
+        let original = "
+hi there. do
+not split
+short sentences.
+hi again.
+";
+
+        // 1回目の処理: 分割
+        let expected = "
+hi there.
+do
+not split
+short sentences.
+hi again.
+";
+        let processed = comply(original, &Options::default());
+        assert_eq!(expected, processed);
+
+        // 2回目の処理: 結合（制限50）
+        let expected = "
+hi there.
+do not split
+short sentences.
+hi again.
+";
+        let processed = lengthen_lines(&processed, &options(50));
+        assert_eq!(expected, processed);
+
+        // 3回目の処理: さらに結合
+        let expected = "
+hi there.
+do not split short sentences.
+hi again.
+";
+        let processed = lengthen_lines(&processed, &options(50));
+        assert_eq!(expected, processed);
+
+        // 整形は収束するまで繰り返すので、1回の呼び出しで同じ結果になる
+        let formatter = Formatter::new(options(50)).unwrap();
+        assert_eq!(expected, formatter.format_str(original));
+        assert_eq!(expected, formatter.format_str(&processed));
+    }
+
+    /// 整形した結果をもう一度整形しても変わらず、レンダリング結果も変わらないことを確認する
+    fn assert_idempotent(formatter: &Formatter, content: &str) {
+        let formatted = formatter.format_str(content);
+        assert_eq!(formatted, formatter.format_str(&formatted), "input: {content:?}");
+        // 全体を範囲に指定すれば、全体を整形した結果と一致する
+        let lines = content.lines().count().max(1);
+        assert_eq!(formatted, formatter.format_range(content, 1..=lines), "input: {content:?}");
+        if let Err(error) = verify(content, &formatted) {
+            panic!("input: {content:?}\n{error}");
+        }
+    }
+
+    /// ガイドの全ての Markdown ファイルで整形が冪等であることのテスト
+    #[test]
+    fn test_idempotence_corpus() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../src");
+        let formatters = [
+            Formatter::new(Options::default()).unwrap(),
+            Formatter::new(Options { max_line_length: Some(100), ..options(80) }).unwrap(),
+        ];
+
+        let mut directories = vec![root];
+        while let Some(directory) = directories.pop() {
+            for entry in fs::read_dir(directory).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    directories.push(path);
+                } else if path.extension().is_some_and(|extension| extension == "md") {
+                    let content = fs::read_to_string(&path).unwrap();
+                    for formatter in &formatters {
+                        assert_idempotent(formatter, &content);
+                    }
+                }
+            }
+        }
+    }
+
+    /// ランダムな Markdown の断片の並び
+    ///
+    /// 文末記号、略語、インライン要素、ブロック構造、mdBook の構文などを組み合わせます。
+    fn markdown() -> impl Strategy<Value = String> {
+        const FRAGMENTS: &[&str] = &[
+            "word ",
+            "word",
+            "words here ",
+            "end. ",
+            "end.",
+            "why? ",
+            "wow! ",
+            "semi; ",
+            "e.g. ",
+            "r? ",
+            "comma, ",
+            "colon: ",
+            "dash — ",
+            " and ",
+            " because ",
+            "`code. x` ",
+            "[link. x](u) ",
+            "*em. x* ",
+            "**太字**",
+            "_x_",
+            "<span>x. y</span> ",
+            "文です。",
+            "読点、",
+            "「はい。」と",
+            "Rustの",
+            "\n",
+            "\n",
+            "\n",
+            "\n\n",
+            "- ",
+            "* ",
+            "1. ",
+            "> ",
+            "  ",
+            "# ",
+            "---\n",
+            "***",
+            "===",
+            "```\n",
+            "~~~\n",
+            "    ",
+            "<div>\n\n",
+            "</div>\n",
+            "<!-- c. d -->",
+            "<!-- sembr-ignore -->\n",
+            "| a | b |\n|---|---|\n",
+            "$$\n",
+            "{{#include a.rs}}",
+            "\\\n",
+            "  \n",
+        ];
+        proptest::collection::vec(proptest::sample::select(FRAGMENTS), 0..80)
+            .prop_map(|fragments| fragments.concat())
+    }
+
+    proptest! {
+        /// ランダムな Markdown で整形が冪等であることのテスト
+        #[test]
+        fn test_idempotence_random(content in markdown()) {
+            for options in [options(30), Options { max_line_length: Some(40), ..options(30) }] {
+                assert_idempotent(&Formatter::new(options).unwrap(), &content);
+            }
+        }
+    }
+
+    /// 疑問符の処理テスト
+    ///
+    /// 疑問符（?）の特殊ケースを正しく処理できることを検証します。
+    /// 特に "r?" (Gitのレビュー依頼) は分割しないようにします。
+    #[test]
+    fn test_sembr_question_mark() {
+        let original = "
+o? whatever
+r? @reviewer
+ r? @reviewer
+";
+
+        let expected = "
+o?
+whatever
+r? @reviewer
+ r? @reviewer
+";
+
+        assert_eq!(expected, comply(original, &Options::default()));
+    }
+
+    /// CommonMark の構造に基づく保護のテスト
+    ///
+    /// テーブル、HTMLブロック、リンク参照定義、インラインコードの内側は
+    /// 分割されず、段落のテキストだけが分割されることを検証します。
+    #[test]
+    fn test_sembr_structure() {
+        let original = "
+prose with `code. span` inside. and more
+<p>
+html. block
+</p>
+
+| cell. one | two |
+|-----------|-----|
+| cell. three | four |
+
+[target]: https://example.com \"title. here\"
+";
+
+        let expected = "
+prose with `code. span` inside.
+and more
+<p>
+html. block
+</p>
+
+| cell. one | two |
+|-----------|-----|
+| cell. three | four |
+
+[target]: https://example.com \"title. here\"
+";
+
+        assert_eq!(expected, comply(original, &Options::default()));
+    }
+
+    /// `|` を含む文章を整形するテスト
+    ///
+    /// クロージャやシェルのパイプ、`||` を含む段落は、テーブルではないので分割・結合されます。
+    #[test]
+    fn test_sembr_pipes() {
+        let original = "
+Closures look like `|x| x + 1`. They capture variables.
+Pipe it: ls | grep x. Then a || b works.
+Short
+line.
+
+| a. b | c |
+|------|---|
+| d. e | f |
+";
+
+        let expected = "
+Closures look like `|x| x + 1`.
+They capture variables.
+Pipe it: ls | grep x.
+Then a || b works.
+Short line.
+
+| a. b | c |
+|------|---|
+| d. e | f |
+";
+
+        let options = options(80);
+        let actual = lengthen_lines(&comply(original, &options), &options);
+        assert_eq!(expected, actual);
+        assert_eq!(structure(original), structure(&actual));
+    }
+
+    /// mdBook 固有の構文を壊さないテスト
+    ///
+    /// 指令や数式ブロックを前後の行と結合したり、途中で分割したりしてはいけません。
+    #[test]
+    fn test_sembr_mdbook() {
+        let original = "
+{{#title Title. With a period}}
+The example is:
+{{#include ../../examples/a.rs}}
+and it prints {{#rustdoc_include a.rs:1:2}} as well. Done.
+Then
+$$
+x = 1. y = 2
+$$
+math. Ends.
+Avoid a new block. $$ here.
+
+```mermaid
+graph TD. A --> B
+```
+";
+
+        let expected = "
+{{#title Title. With a period}}
+The example is:
+{{#include ../../examples/a.rs}}
+and it prints {{#rustdoc_include a.rs:1:2}} as well.
+Done.
+Then
+$$
+x = 1. y = 2
+$$
+math.
+Ends.
+Avoid a new block. $$ here.
+
+```mermaid
+graph TD. A --> B
+```
+";
+
+        let options = options(80);
+        assert_eq!(expected, lengthen_lines(&comply(original, &options), &options));
+    }
+
+    /// 整形を抑制するコメントのテスト
+    #[test]
+    fn test_sembr_suppression() {
+        let original = "
+<!-- sembr-ignore -->
+Roses are red. Violets are blue.
+Short
+line.
+
+<!-- sembr-ignore-start -->
+    ASCII diagram. Kept as is.
+Aligned. Columns.
+Short
+line.
+<!-- sembr-ignore-end -->
+
+Formatted. As usual.
+Short
+line.
+";
+
+        let expected = "
+<!-- sembr-ignore -->
+Roses are red. Violets are blue.
+Short
+line.
+
+<!-- sembr-ignore-start -->
+    ASCII diagram. Kept as is.
+Aligned. Columns.
+Short
+line.
+<!-- sembr-ignore-end -->
+
+Formatted.
+As usual.
+Short line.
+";
+
+        let formatter = Formatter::new(options(80)).unwrap();
+        assert_eq!(expected, formatter.format_str(original));
+        assert!(formatter.warnings(original).is_empty());
+
+        let ignored = "<!-- sembr-ignore-file -->\n\nNot. Formatted.\n";
+        assert_eq!(ignored, formatter.format_str(ignored));
+
+        let unterminated = "Formatted. Here.\n<!-- sembr-ignore-start -->\n\nNot. Here.\n";
+        assert_eq!(
+            "Formatted.\nHere.\n<!-- sembr-ignore-start -->\n\nNot. Here.\n",
+            formatter.format_str(unterminated)
+        );
+        let warnings = formatter.warnings(unterminated);
+        assert_eq!(1, warnings.len());
+        assert_eq!((2, WarningKind::UnterminatedIgnore), (warnings[0].line, warnings[0].kind));
+    }
+
+    /// 最大行長を超える文を節の境界で分割するテスト
+    #[test]
+    fn test_sembr_max_line_length() {
+        let original = "
+This sentence is quite long, it has a comma and a clause, and it keeps on going until the end.
+- A list item mentions `a, b, c` inside code and then goes on because it has to.
+The value is computed lazily — only when it is needed by the caller of this function.
+この文はとても長いので、読点の位置で分割されます、そして最後まで続きます。
+Averyveryveryveryveryveryveryverylongwordwithoutanyclauseboundaryatallthatcannotbesplit.
+Short, fine.
+";
+
+        let expected = "
+This sentence is quite long, it has a comma and a clause,
+and it keeps on going until the end.
+- A list item mentions `a, b, c` inside code
+  and then goes on because it has to.
+The value is computed lazily —
+only when it is needed by the caller of this function.
+この文はとても長いので、読点の位置で分割されます、
+そして最後まで続きます。
+Averyveryveryveryveryveryveryverylongwordwithoutanyclauseboundaryatallthatcannotbesplit.
+Short, fine.
+";
+
+        let options = Options { max_line_length: Some(60), ..options(100) };
+        let formatter = Formatter::new(options).unwrap();
+        let formatted = formatter.format_str(original);
+        assert_eq!(expected, formatted);
+
+        // 整形結果は安定しており、収まらない行だけが警告される
+        assert_eq!(formatted, formatter.format_str(&formatted));
+        let warnings = formatter.warnings(&formatted);
+        assert_eq!(1, warnings.len());
+        assert_eq!((10, WarningKind::LineTooLong), (warnings[0].line, warnings[0].kind));
+
+        // 最大行長を指定しなければ、節の境界では分割しない
+        assert_eq!(original, comply(original, &Options::default()));
+    }
+
+    /// setext 見出し・テーマ区切り・ATX 見出しを壊さないテスト
+    ///
+    /// 分割で行頭にこれらの記号が来ると、段落が見出しやテーマ区切りに変わってしまいます。
+    /// また、見出しの行を下線やテーマ区切りと結合してはいけません。
+    #[test]
+    fn test_sembr_setext_and_thematic_breaks() {
+        let original = "
+Title
+=====
+
+Sub
+title
+---
+
+short
+***
+text
+- - -
+
+Underline it. ===
+
+Or this. ---
+
+A rule. ***
+
+Or this. - - -
+
+A heading. # Title
+
+Issue number. #1 is fine.
+";
+
+        let expected = "
+Title
+=====
+
+Sub
+title
+---
+
+short
+***
+text
+- - -
+
+Underline it. ===
+
+Or this. ---
+
+A rule. ***
+
+Or this. - - -
+
+A heading. # Title
+
+Issue number.
+#1 is fine.
+";
+
+        let options = options(80);
+        let actual = lengthen_lines(&comply(original, &options), &options);
+        assert_eq!(expected, actual);
+        assert_eq!(structure(original), structure(&actual));
+    }
+
+    /// レンダリング結果を変えてしまう分割・結合をしないテスト
+    ///
+    /// いずれも [`verify`] で検出された整形の不具合です。
+    #[test]
+    fn test_sembr_preserves_rendering() {
+        let formatter = Formatter::new(options(80)).unwrap();
+        let cases = [
+            // 行頭の `<!--` は段落を中断して HTML ブロックになる
+            ("semi; <!-- c. d -->\n", "semi; <!-- c. d -->\n"),
+            // 最初の行だけになるとリンク参照定義になる
+            ("[`name`]: 説明です。続きです。\n", "[`name`]: 説明です。続きです。\n"),
+            // ヘッダー行の列数が区切り行と揃うとテーブルになる
+            ("end. | a | b |\n|---|---|\n", "end. | a | b |\n|---|---|\n"),
+            // 行末の2つの空白（ハード改行）を残す
+            ("why? word  \nnext\n", "why?\nword  \nnext\n"),
+            // `*` だけの行と結合するとリスト項目になる
+            ("word ```\n* \nword word\n", "word ```\n* \nword word\n"),
+            // 強調の区切り文字の隣は空白を入れて結合する
+            (
+                "それがわかりません。**場合、**\nそれを追跡します。\n",
+                "それがわかりません。\n**場合、** それを追跡します。\n",
+            ),
+        ];
+        for (original, expected) in cases {
+            let actual = formatter.format_str(original);
+            assert_eq!(expected, actual, "input: {original:?}");
+            assert!(verify(original, &actual).is_ok(), "input: {original:?}");
+        }
+
+        // 節の境界での分割でも、テーマ区切りや空行を作らない
+        let formatter =
+            Formatter::new(Options { max_line_length: Some(20), ..options(10) }).unwrap();
+        for original in ["文です。*** because word word\n", "word end.  and word word word\n"] {
+            let actual = formatter.format_str(original);
+            assert!(verify(original, &actual).is_ok(), "input: {original:?}, output: {actual:?}");
+        }
+    }
+
+    /// 引用ブロックとネストしたリスト項目の内側を整形するテスト
+    ///
+    /// 分割で追加した行には継続行の接頭辞を付け、
+    /// 結合では次の行の接頭辞を取り除きます。
+    #[test]
+    fn test_sembr_containers() {
+        let original = "
+> A quoted note. It has two sentences.
+> Short
+> line.
+
+> - Quoted item. Continues here.
+>   Next
+>   line.
+>
+>   Second paragraph. In the item.
+
+1. First item. More text.
+   - Nested item. More nested text.
+
+     Loose paragraph. Still nested.
+     Short
+     line.
+
+> > Deeply quoted. Text. - not an item
+";
+
+        let expected = "
+> A quoted note.
+> It has two sentences.
+> Short line.
+
+> - Quoted item.
+>   Continues here.
+>   Next line.
+>
+>   Second paragraph.
+>   In the item.
+
+1. First item.
+   More text.
+   - Nested item.
+     More nested text.
+
+     Loose paragraph.
+     Still nested.
+     Short line.
+
+> > Deeply quoted.
+> > Text. - not an item
+";
+
+        let options = options(80);
+        let actual = lengthen_lines(&comply(original, &options), &options);
+        assert_eq!(expected, actual);
+        assert_eq!(structure(original), structure(&actual));
+    }
+
+    /// インライン要素の内側で分割しないテスト
+    ///
+    /// インラインコード、リンクテキスト、リンク先、自動リンク、
+    /// インライン HTML、強調はそれぞれ一つの単位として扱われることを検証します。
+    #[test]
+    fn test_sembr_inline_atomic() {
+        let original = "
+code `foo. bar` here. next
+link [see the docs. Then](<https://example.com/a. b>) here. next
+image ![alt. text](img.png) here. next
+auto <https://example.com/a.b> here. next
+html <span title=\"a. b\">x</span> here. next
+inline html <span>x. y</span> here. next
+void <br> one. two
+emphasis *foo. bar* and **baz. qux** here. next
+strike ~~foo. bar~~ here. next
+日本語の`コード。内側`です。次の文。
+日本語の[リンク。内側](url)です。次の文。
+";
+
+        let expected = "
+code `foo. bar` here.
+next
+link [see the docs. Then](<https://example.com/a. b>) here.
+next
+image ![alt. text](img.png) here.
+next
+auto <https://example.com/a.b> here.
+next
+html <span title=\"a. b\">x</span> here.
+next
+inline html <span>x. y</span> here.
+next
+void <br> one.
+two
+emphasis *foo. bar* and **baz. qux** here.
+next
+strike ~~foo. bar~~ here.
+next
+日本語の`コード。内側`です。
+次の文。
+日本語の[リンク。内側](url)です。
+次の文。
+";
+
+        assert_eq!(expected, comply(original, &Options::default()));
+    }
+
+    /// 略語の位置だけで分割を抑制するテスト
+    ///
+    /// 略語を含む行でも、略語以外の文の境界では分割されることを検証します。
+    #[test]
+    fn test_sembr_abbreviations() {
+        let original = "
+see e.g. the docs. then this, i.e. that. done
+Compare A vs. B etc. and more. next
+See Dr. Smith. also cf. below
+eg. is not e.g. and size. matters
+";
+
+        let expected = "
+see e.g. the docs.
+then this, i.e. that.
+done
+Compare A vs. B etc. and more.
+next
+See Dr. Smith.
+also cf. below
+eg.
+is not e.g. and size.
+matters
+";
+
+        assert_eq!(expected, comply(original, &Options::default()));
+
+        // 略語を追加できる
+        let mut options = Options::default();
+        options.abbreviations.push("Fig.".to_owned());
+        assert_eq!("see fig. 1\n", comply("see fig. 1\n", &options));
+    }
+
+    /// 略語で終わる行の結合テスト
+    #[test]
+    fn test_prettify_abbreviations() {
+        let original = "\
+for example, e.g.
+this one
+";
+
+        let expected = "\
+for example, e.g. this one
+";
+
+        assert_eq!(expected, lengthen_lines(original, &options(50)));
+    }
+
+    /// 設定による文末記号と分割しない位置のテスト
+    #[test]
+    fn test_sembr_configured_rules() {
+        let original = "
+項目A：説明です。 Note: see below
+please review r? @reviewer thanks! ok
+";
+
+        let expected = "
+項目A：
+説明です。
+Note:
+see below
+please review r? @reviewer thanks!
+ok
+";
+
+        let options = Options {
+            extra_terminators: vec!["：".to_owned(), ":".to_owned()],
+            ..Options::default()
+        };
+        assert_eq!(expected, comply(original, &options));
+
+        // 分割しない位置の既定値を置き換えると、r? でも分割される
+        let expected = "
+項目A：説明です。
+Note: see below
+please review r?
+@reviewer thanks!
+ok
+";
+        let options = Options { never_split_after: Vec::new(), ..Options::default() };
+        assert_eq!(expected, comply(original, &options));
+    }
+
+    /// コードブロックの保護と、分割でコードフェンスを作らないことのテスト
+    #[test]
+    fn test_sembr_code_fences() {
+        let original = "
+````markdown
+```rust
+Not prose. Keep it.
+```
+````
+
+~~~
+tilde. fence
+~~~
+
+    indented. code
+
+Use a fence. ~~~ opens one.
+Close with backticks. ``` works too. Really.
+";
+
+        let expected = "
+````markdown
+```rust
+Not prose. Keep it.
+```
+````
+
+~~~
+tilde. fence
+~~~
+
+    indented. code
+
+Use a fence. ~~~ opens one.
+Close with backticks. ``` works too.
+Really.
+";
+
+        assert_eq!(expected, comply(original, &Options::default()));
+    }
+
+    /// HTML 要素とコメントの内側を分割・結合しないことのテスト
+    #[test]
+    fn test_sembr_html() {
+        let original = "
+<div class=\"warning\">
+
+Inside a div. Not split.
+Not
+joined.
+
+</div>
+
+<details>
+<summary>Summary. Here</summary>
+<table>
+<tr><td>cell. one</td></tr>
+</table>
+<pre>
+pre. formatted
+</pre>
+
+Inside details. Not split.
+</details>
+
+<!--
+A comment. Spanning lines.
+-->
+
+Dates are checked <!-- date-check: Jan 2025. Keep
+this comment. Intact --> in this paragraph. Split here.
+Short
+line.
+";
+
+        let expected = "
+<div class=\"warning\">
+
+Inside a div. Not split.
+Not
+joined.
+
+</div>
+
+<details>
+<summary>Summary. Here</summary>
+<table>
+<tr><td>cell. one</td></tr>
+</table>
+<pre>
+pre. formatted
+</pre>
+
+Inside details. Not split.
+</details>
+
+<!--
+A comment. Spanning lines.
+-->
+
+Dates are checked <!-- date-check: Jan 2025. Keep
+this comment. Intact --> in this paragraph.
+Split here.
+Short line.
+";
+
+        let options = options(80);
+        assert_eq!(expected, lengthen_lines(&comply(original, &options), &options));
+    }
+
+    /// 公開 API（`format_str`、`check_str`、エラー）のテスト
+    #[test]
+    fn test_formatter() {
+        let formatter = Formatter::new(Options::default()).unwrap();
+
+        let original = "First. Second.\n\nshort\nline\n";
+        assert_eq!("First.\nSecond.\n\nshort line\n", formatter.format_str(original));
+
+        let edits = formatter.check_str(original);
+        assert_eq!(2, edits.len());
+        assert_eq!(
+            (1, 1, Rule::NeedsSplit),
+            (edits[0].start_line, edits[0].end_line, edits[0].rule)
+        );
+        assert_eq!((3, 4, Rule::CanJoin), (edits[1].start_line, edits[1].end_line, edits[1].rule));
+
+        // 適合しているテキストには変更がない
+        assert!(formatter.check_str("First.\nSecond.\n").is_empty());
+
+        // 範囲に重なる段落だけを整形する
+        let original = "First. Second.\n\nshort\nline\n\nThird. Fourth.\n";
+        assert_eq!(
+            "First. Second.\n\nshort line\n\nThird. Fourth.\n",
+            formatter.format_range(original, 4..=4)
+        );
+        assert_eq!(
+            "First.\nSecond.\n\nshort\nline\n\nThird. Fourth.\n",
+            formatter.format_range(original, 1..=1)
+        );
+        assert_eq!(formatter.format_str(original), formatter.format_range(original, 1..=7));
+
+        // 複数の範囲のいずれかに重なる段落を整形する
+        assert_eq!(
+            "First.\nSecond.\n\nshort\nline\n\nThird.\nFourth.\n",
+            formatter.format_ranges(original, &[1..=1, 6..=6])
+        );
+        assert_eq!(original, formatter.format_ranges(original, &[]));
+
+        // 分割で強調やインラインコード、リンクだけの行ができても、段落を対応付けられる
+        for original in
+            ["It works! *Really*\n", "Done! `code`\n", "Done! [link](u)\n", "文です。**太字**\n"]
+        {
+            assert_eq!(formatter.format_str(original), formatter.format_range(original, 1..=1));
+        }
+
+        // 改行コード、BOM、末尾の改行の有無は元のテキストに合わせる
+        assert_eq!("First.\r\nSecond.\r\n", formatter.format_str("First. Second.\r\n"));
+        assert_eq!("First.\nSecond.", formatter.format_str("First. Second."));
+        assert_eq!(
+            "\u{FEFF}# title\n\nshort line\n",
+            formatter.format_str("\u{FEFF}# title\n\nshort\nline\n")
+        );
+        assert_eq!("", formatter.format_str(""));
+        assert_eq!(
+            "First. Second.\r\n\r\nshort line",
+            formatter.format_range("First. Second.\r\n\r\nshort\r\nline", 3..=3)
+        );
+        let edits = formatter.check_str("# title\r\n\r\nFirst. Second.\r\n");
+        assert_eq!("First.\r\nSecond.\r\n", edits[0].replacement);
+        assert!(formatter.check_str("First.\r\nSecond.").is_empty());
+
+        // 改行コードが混在していれば、統一する設定がない限り整形せずに警告する
+        let mixed = "First. Second.\r\n\r\nshort\nline\n";
+        assert_eq!(mixed, formatter.format_str(mixed));
+        assert_eq!(mixed, formatter.format_range(mixed, 1..=4));
+        assert!(formatter.check_str(mixed).is_empty());
+        let warnings = formatter.warnings(mixed);
+        assert_eq!((3, WarningKind::MixedLineEndings), (warnings[0].line, warnings[0].kind));
+
+        // 設定した場合のみ統一する
+        let formatter = Formatter::new(Options {
+            line_ending: LineEnding::Lf,
+            insert_final_newline: true,
+            strip_bom: true,
+            ..Options::default()
+        })
+        .unwrap();
+        assert_eq!("First.\nSecond.\n", formatter.format_str("\u{FEFF}First.\r\nSecond."));
+        assert_eq!("First.\nSecond.\n\nshort line\n", formatter.format_str(mixed));
+        assert!(formatter.warnings(mixed).is_empty());
+
+        // 不正な正規表現は作成時に検出する
+        let options = Options { never_split_after: vec!["(".to_owned()], ..Options::default() };
+        assert!(matches!(Formatter::new(options), Err(Error::InvalidPattern { .. })));
+    }
+
+    /// 全角の句読点による分割のテスト
+    ///
+    /// 日本語の文は句読点の後に空白がなくても分割され、
+    /// 閉じ括弧は終える文の側に残ることを検証します。
+    #[test]
+    fn test_sembr_cjk() {
+        let original = "
+これは一文目です。これは二文目です！本当に？はい；そうです。
+「括弧の中の文。」次の文。（補足です。）続き。
+彼は「はい。」と言った。次の文。
+- リストの項目です。続きの文です。
+英語の文。 English follows.
+行末の句点はそのまま。
+```
+コード。ブロック。
+```
+";
+
+        let expected = "
+これは一文目です。
+これは二文目です！
+本当に？
+はい；
+そうです。
+「括弧の中の文。」
+次の文。
+（補足です。）
+続き。
+彼は「はい。」と言った。
+次の文。
+- リストの項目です。
+  続きの文です。
+英語の文。
+English follows.
+行末の句点はそのまま。
+```
+コード。ブロック。
+```
+";
+
+        assert_eq!(expected, comply(original, &Options::default()));
+    }
+}
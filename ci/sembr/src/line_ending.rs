@@ -0,0 +1,170 @@
+//! 改行コード、BOM、末尾の改行の保持
+//!
+//! 整形処理は、LF で区切られ、BOM のないテキストを前提にしています。
+//! 整形の前に元のテキストの形式を記録してこれらを取り除き、整形後に同じ形式に戻すことで、
+//! CRLF のファイルや末尾に改行のないファイルを、整形と関係のない部分まで書き換えないようにします。
+//! 形式の統一は、[`Options`] で指定した場合のみ行います。
+
+use std::borrow::Cow;
+
+use serde::Deserialize;
+
+use crate::Options;
+
+/// UTF-8 の BOM（バイト順マーク）
+const BOM: char = '\u{FEFF}';
+
+/// 整形結果の改行コード
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum LineEnding {
+    /// 元のテキストに合わせる
+    ///
+    /// LF と CRLF が混在しているテキストは、改行コードを変えずには整形できないため、
+    /// 整形せずにそのまま残します（[`WarningKind::MixedLineEndings`] で警告します）。
+    ///
+    /// [`WarningKind::MixedLineEndings`]: crate::WarningKind::MixedLineEndings
+    #[default]
+    Preserve,
+    /// LF（`\n`）に統一する
+    Lf,
+    /// CRLF（`\r\n`）に統一する
+    Crlf,
+}
+
+/// 整形結果に適用するテキストの形式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct TextStyle {
+    /// 先頭に BOM を付けるかどうか
+    bom: bool,
+    /// 改行コードを CRLF にするかどうか
+    crlf: bool,
+    /// 末尾に改行を付けるかどうか
+    final_newline: bool,
+}
+
+impl TextStyle {
+    /// 元のテキストと設定から、整形結果の形式を決定する
+    ///
+    /// # 引数
+    /// - `content`: 整形前のテキスト
+    /// - `options`: 整形処理の設定（[`Options::line_ending`]、
+    ///   [`Options::insert_final_newline`]、[`Options::strip_bom`]）
+    ///
+    /// # 戻り値
+    /// 整形結果の形式。
+    /// [`LineEnding::Preserve`] で LF と CRLF が混在している場合は、
+    /// 改行コードを変えずに整形できないため `None`。
+    ///
+    /// # 使用例
+    /// ```rust,ignore
+    /// let style = TextStyle::detect("a\r\nb", &Options::default()).unwrap();
+    /// assert_eq!(style.apply("a\nb\n".to_owned()), "a\r\nb");
+    /// assert_eq!(TextStyle::detect("a\r\nb\n", &Options::default()), None);
+    /// ```
+    pub(crate) fn detect(content: &str, options: &Options) -> Option<Self> {
+        let bom = content.starts_with(BOM) && !options.strip_bom;
+        let crlf = match options.line_ending {
+            LineEnding::Preserve if mixed_line_endings(content).is_some() => return None,
+            LineEnding::Preserve => {
+                content.find('\n').is_some_and(|end| content[..end].ends_with('\r'))
+            }
+            LineEnding::Lf => false,
+            LineEnding::Crlf => true,
+        };
+        // 空のテキストには、改行を付ける最後の行がない
+        let final_newline =
+            content.ends_with('\n') || (options.insert_final_newline && !content.is_empty());
+        Some(Self { bom, crlf, final_newline })
+    }
+
+    /// 整形処理が前提とする LF のみの改行で終わるテキストを、この形式に変換する
+    pub(crate) fn apply(self, mut content: String) -> String {
+        if !self.final_newline && content.ends_with('\n') {
+            content.pop();
+        }
+        if self.crlf {
+            content = content.replace('\n', "\r\n");
+        }
+        if self.bom {
+            content.insert(0, BOM);
+        }
+        content
+    }
+}
+
+/// LF と CRLF が混在している場合に、最初の行と改行コードが異なる行を求める
+///
+/// # 戻り値
+/// 混在していれば、最初の行と異なる改行コードで終わる最初の行（1始まり）
+///
+/// # 使用例
+/// ```rust,ignore
+/// assert_eq!(mixed_line_endings("a\r\nb\r\nc\n"), Some(3));
+/// assert_eq!(mixed_line_endings("a\r\nb\r\nc"), None);
+/// ```
+pub(crate) fn mixed_line_endings(content: &str) -> Option<usize> {
+    let mut endings = content
+        .split_inclusive('\n')
+        .enumerate()
+        .filter(|(_, line)| line.ends_with('\n'))
+        .map(|(n, line)| (n + 1, line.ends_with("\r\n")));
+    let (_, first) = endings.next()?;
+    endings.find(|&(_, crlf)| crlf != first).map(|(line, _)| line)
+}
+
+/// 整形処理の前に、BOM を取り除き、CRLF を LF に変換する
+///
+/// 行の数と順序は変わらないため、行番号は元のテキストと一致します。
+pub(crate) fn strip(content: &str) -> Cow<'_, str> {
+    let content = content.strip_prefix(BOM).unwrap_or(content);
+    if content.contains('\r') {
+        Cow::Owned(content.replace("\r\n", "\n"))
+    } else {
+        Cow::Borrowed(content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LineEnding, TextStyle, mixed_line_endings, strip};
+    use crate::Options;
+
+    /// 元の形式の保持と、設定による統一のテスト
+    #[test]
+    fn test_text_style() {
+        let preserve = Options::default();
+        // 整形処理と同じく、改行で終わるテキストに変換してから戻す
+        let restore = |content: &str, options: &Options| {
+            let mut formatted = strip(content).into_owned();
+            if !formatted.ends_with('\n') {
+                formatted.push('\n');
+            }
+            TextStyle::detect(content, options).map(|style| style.apply(formatted))
+        };
+
+        // 既定では、元のテキストをそのまま戻す
+        for content in ["a\nb\n", "a\r\nb\r\n", "a\r\nb", "\u{FEFF}a\nb\n", "", "a"] {
+            assert_eq!(Some(content.to_owned()), restore(content, &preserve));
+        }
+
+        // 改行コードが混在していれば、既定では整形しない
+        assert_eq!(None, restore("a\r\nb\n", &preserve));
+        assert_eq!(Some(3), mixed_line_endings("a\nb\nc\r\nd"));
+        assert_eq!(None, mixed_line_endings("a\r\nb\r\nc"));
+        assert_eq!(None, mixed_line_endings(""));
+
+        // 設定した場合のみ統一する
+        let options = Options { line_ending: LineEnding::Lf, ..Options::default() };
+        assert_eq!(Some("a\nb\n".to_owned()), restore("a\r\nb\r\n", &options));
+        assert_eq!(Some("a\nb\n".to_owned()), restore("a\r\nb\n", &options));
+        let options = Options { line_ending: LineEnding::Crlf, ..Options::default() };
+        assert_eq!(Some("a\r\nb\r\n".to_owned()), restore("a\nb\n", &options));
+        let options = Options { insert_final_newline: true, ..Options::default() };
+        assert_eq!(Some("a\n".to_owned()), restore("a", &options));
+        assert_eq!(Some(String::new()), restore("", &options));
+        let options = Options { strip_bom: true, ..Options::default() };
+        assert_eq!(Some("a\n".to_owned()), restore("\u{FEFF}a\n", &options));
+    }
+}
@@ -0,0 +1,538 @@
+//! Language Server Protocol モード（`sembr lsp`）
+//!
+//! CI で「not compliant」と報告されてから直すのではなく、
+//! 編集中にセマンティックラインブレークの問題に気付けるように、
+//! 標準入出力で通信する言語サーバーとして動作します。
+//!
+//! # 提供する機能
+//! - 診断: 開いている Markdown 文書の `needs-split` と `can-join` の変更箇所
+//! - クイックフィックス: 各変更箇所を整形後のテキストに置き換えるコードアクション
+//! - 整形: `textDocument/formatting` と `textDocument/rangeFormatting`
+//!
+//! 文書の同期は全文（`TextDocumentSyncKind::FULL`）で行います。
+//! 整形規則は、文書のパスから探索した `sembr.toml` に従います。
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Result;
+use lsp_server::{Connection, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, PublishDiagnostics,
+};
+use lsp_types::request::{CodeActionRequest, Formatting, RangeFormatting};
+use lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, CodeActionParams,
+    CodeActionProviderCapability, Diagnostic, DiagnosticSeverity, DidChangeTextDocumentParams,
+    DidCloseTextDocumentParams, DidOpenTextDocumentParams, DocumentFormattingParams,
+    DocumentRangeFormattingParams, NumberOrString, OneOf, Position, PublishDiagnosticsParams,
+    Range, ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, TextEdit, Url,
+    WorkspaceEdit,
+};
+use sembr::config::{Config, Rules};
+use sembr::{Edit, Formatter};
+
+/// 診断の発行元として表示する名前
+const SOURCE: &str = "sembr";
+
+/// 開いている文書
+struct Document {
+    /// 文書の現在の内容
+    text: String,
+
+    /// 文書に適用する整形器
+    formatter: Formatter,
+
+    /// 設定ファイルの `include`/`exclude` でチェック対象になっているか
+    ///
+    /// 対象外の文書には診断を発行しませんが、明示的な整形の要求には応じます。
+    included: bool,
+}
+
+/// 言語サーバーの状態
+struct Server {
+    /// 全文書に使う設定（`--config` が指定された場合）
+    ///
+    /// `None` なら、文書ごとにそのパスから設定ファイルを探索します。
+    config: Option<Config>,
+
+    /// 開いている文書（URI ごと）
+    documents: HashMap<Url, Document>,
+}
+
+/// 言語サーバーを起動し、クライアントが終了するまで処理する
+///
+/// # 引数
+/// - `config`: 全文書に使う設定（`None` なら文書ごとに探索）
+pub fn run(config: Option<Config>) -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        document_range_formatting_provider: Some(OneOf::Left(true)),
+        ..ServerCapabilities::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+
+    let mut server = Server { config, documents: HashMap::new() };
+
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    break;
+                }
+                let response = server.handle_request(request);
+                connection.sender.send(Message::Response(response))?;
+            }
+            Message::Notification(notification) => {
+                match server.handle_notification(notification) {
+                    Ok(Some(diagnostics)) => {
+                        let notification = Notification::new(
+                            <PublishDiagnostics as lsp_types::notification::Notification>::METHOD
+                                .to_owned(),
+                            diagnostics,
+                        );
+                        connection.sender.send(Message::Notification(notification))?;
+                    }
+                    Ok(None) => {}
+                    // 設定ファイルの誤りなどでサーバーを止めないように、標準エラーに記録するだけにする
+                    Err(error) => eprintln!("sembr: {error:#}"),
+                }
+            }
+            Message::Response(_) => {}
+        }
+    }
+
+    // 書き込みスレッドが終了できるように、送信側を閉じてから待つ
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+impl Server {
+    /// リクエストを処理して応答を作成する
+    ///
+    /// 未対応のメソッドには `MethodNotFound` を返します。
+    fn handle_request(&self, request: Request) -> Response {
+        let id = request.id.clone();
+        let result = match request.method.as_str() {
+            <CodeActionRequest as lsp_types::request::Request>::METHOD => {
+                extract::<CodeActionParams>(request)
+                    .map(|params| serde_json::to_value(self.code_actions(&params)))
+            }
+            <Formatting as lsp_types::request::Request>::METHOD => {
+                extract::<DocumentFormattingParams>(request)
+                    .map(|params| serde_json::to_value(self.formatting(&params.text_document.uri)))
+            }
+            <RangeFormatting as lsp_types::request::Request>::METHOD => extract::<
+                DocumentRangeFormattingParams,
+            >(request)
+            .map(|params| {
+                serde_json::to_value(self.range_formatting(&params.text_document.uri, params.range))
+            }),
+            method => {
+                return Response::new_err(
+                    id,
+                    lsp_server::ErrorCode::MethodNotFound as i32,
+                    format!("unsupported request `{method}`"),
+                );
+            }
+        };
+
+        match result {
+            Ok(Ok(value)) => Response::new_ok(id, value),
+            Ok(Err(error)) => Response::new_err(
+                id,
+                lsp_server::ErrorCode::InternalError as i32,
+                error.to_string(),
+            ),
+            Err(message) => {
+                Response::new_err(id, lsp_server::ErrorCode::InvalidParams as i32, message)
+            }
+        }
+    }
+
+    /// 通知を処理する
+    ///
+    /// # 戻り値
+    /// クライアントに発行する診断（文書を開いた・変更した・閉じた場合）
+    fn handle_notification(
+        &mut self,
+        notification: Notification,
+    ) -> Result<Option<PublishDiagnosticsParams>> {
+        use lsp_types::notification::Notification as _;
+
+        let uri = match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: DidOpenTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                let document = params.text_document;
+                self.open(document.uri.clone(), document.text)?;
+                document.uri
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: DidChangeTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                // 全文同期なので、最後の変更が文書全体の内容
+                let (Some(document), Some(change)) = (
+                    self.documents.get_mut(&params.text_document.uri),
+                    params.content_changes.last(),
+                ) else {
+                    return Ok(None);
+                };
+                document.text.clone_from(&change.text);
+                params.text_document.uri
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: DidCloseTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                self.documents.remove(&params.text_document.uri);
+                // 閉じた文書の診断を消す
+                return Ok(Some(PublishDiagnosticsParams {
+                    uri: params.text_document.uri,
+                    diagnostics: Vec::new(),
+                    version: None,
+                }));
+            }
+            _ => return Ok(None),
+        };
+
+        Ok(Some(PublishDiagnosticsParams {
+            diagnostics: self.diagnostics(&uri),
+            uri,
+            version: None,
+        }))
+    }
+
+    /// 文書を開き、その文書に適用する整形器を用意する
+    ///
+    /// `file:` の URI なら、そのパスから設定ファイルを探索します。
+    fn open(&mut self, uri: Url, text: String) -> Result<()> {
+        let path = uri.to_file_path().ok();
+        let discovered;
+        let config = match (&self.config, &path) {
+            (Some(config), _) => config,
+            (None, Some(path)) => {
+                discovered = Config::discover(path.parent().unwrap_or(Path::new(".")))?;
+                &discovered
+            }
+            (None, None) => {
+                discovered = Config::default();
+                &discovered
+            }
+        };
+
+        let (options, included) = match &path {
+            Some(path) => (config.options_for(path, &Rules::default()), config.is_included(path)),
+            None => (config.options_for(Path::new(""), &Rules::default()), true),
+        };
+        let formatter = Formatter::new(options)?;
+
+        self.documents.insert(uri, Document { text, formatter, included });
+        Ok(())
+    }
+
+    /// 文書の診断を求める
+    ///
+    /// 整形による行単位の変更（[`Edit`]）ごとに、1つの警告を作成します。
+    /// 整形を抑制するコメントの誤り（[`sembr::Warning`]）も、その行の警告にします。
+    fn diagnostics(&self, uri: &Url) -> Vec<Diagnostic> {
+        let Some(document) = self.documents.get(uri).filter(|document| document.included) else {
+            return Vec::new();
+        };
+
+        let warnings = document.formatter.warnings(&document.text).into_iter().map(|warning| {
+            let line = warning.line as u32 - 1;
+            Diagnostic {
+                range: Range::new(Position::new(line, 0), line_start(&document.text, line + 1)),
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(NumberOrString::String(warning.kind.id().to_owned())),
+                source: Some(SOURCE.to_owned()),
+                message: warning.kind.description().to_owned(),
+                ..Diagnostic::default()
+            }
+        });
+
+        document
+            .formatter
+            .check_str(&document.text)
+            .iter()
+            .map(|edit| Diagnostic {
+                range: edit_range(edit, &document.text),
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(NumberOrString::String(edit.rule.id().to_owned())),
+                source: Some(SOURCE.to_owned()),
+                message: edit.rule.description().to_owned(),
+                ..Diagnostic::default()
+            })
+            .chain(warnings)
+            .collect()
+    }
+
+    /// 要求された範囲に重なる変更箇所のクイックフィックスを求める
+    ///
+    /// 診断の内容を信用せず、文書の現在の内容から変更を求め直します。
+    /// 整形と同じく、変更を適用するとレンダリング結果が変わる場合（[`sembr::verify`]）は、
+    /// クイックフィックスを作成しません。
+    fn code_actions(&self, params: &CodeActionParams) -> Vec<CodeActionOrCommand> {
+        let uri = &params.text_document.uri;
+        let Some(document) = self.documents.get(uri) else {
+            return Vec::new();
+        };
+
+        document
+            .formatter
+            .check_str(&document.text)
+            .iter()
+            .filter(|edit| overlaps(edit_range(edit, &document.text), params.range))
+            .filter(|edit| sembr::verify(&document.text, &apply_edit(&document.text, edit)).is_ok())
+            .map(|edit| {
+                let range = edit_range(edit, &document.text);
+                // 同じ範囲の診断があれば、このアクションで解決されるものとして紐付ける
+                let diagnostics: Vec<_> = params
+                    .context
+                    .diagnostics
+                    .iter()
+                    .filter(|diagnostic| {
+                        diagnostic.source.as_deref() == Some(SOURCE) && diagnostic.range == range
+                    })
+                    .cloned()
+                    .collect();
+                let title = match edit.rule {
+                    sembr::Rule::NeedsSplit => "Split sentences onto separate lines",
+                    sembr::Rule::CanJoin => "Join short lines",
+                };
+                let changes = HashMap::from([(
+                    uri.clone(),
+                    vec![TextEdit::new(range, edit.replacement.clone())],
+                )]);
+
+                CodeActionOrCommand::CodeAction(CodeAction {
+                    title: title.to_owned(),
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    diagnostics: (!diagnostics.is_empty()).then_some(diagnostics),
+                    edit: Some(WorkspaceEdit {
+                        changes: Some(changes),
+                        ..WorkspaceEdit::default()
+                    }),
+                    is_preferred: Some(true),
+                    ..CodeAction::default()
+                })
+            })
+            .collect()
+    }
+
+    /// 文書全体を整形する
+    ///
+    /// # 戻り値
+    /// 文書全体を置き換える編集（変更がなければ空）
+    fn formatting(&self, uri: &Url) -> Vec<TextEdit> {
+        let Some(document) = self.documents.get(uri) else {
+            return Vec::new();
+        };
+        replace_all(&document.text, document.formatter.format_str(&document.text))
+    }
+
+    /// 範囲に重なる段落だけを整形する
+    fn range_formatting(&self, uri: &Url, range: Range) -> Vec<TextEdit> {
+        let Some(document) = self.documents.get(uri) else {
+            return Vec::new();
+        };
+        // LSP の行番号は0始まり、`format_range` は1始まり
+        // 選択範囲が行頭で終わる場合、その行は選択されていない（空の選択範囲を除く）
+        let end = if range.end.character == 0 && range.end.line > range.start.line {
+            range.end.line
+        } else {
+            range.end.line + 1
+        };
+        let lines = range.start.line as usize + 1..=end as usize;
+        replace_all(&document.text, document.formatter.format_range(&document.text, lines))
+    }
+}
+
+/// リクエストのパラメータを取り出す
+fn extract<P: serde::de::DeserializeOwned>(request: Request) -> Result<P, String> {
+    serde_json::from_value(request.params).map_err(|error| error.to_string())
+}
+
+/// 変更が置き換える行範囲を LSP の範囲に変換する
+///
+/// 行全体を置き換えるため、範囲は最初の行の先頭から、最後の行の次の行の先頭までです。
+/// 末尾に改行のない文書の最後の行では、文書の末尾までです（[`line_start`]）。
+fn edit_range(edit: &Edit, text: &str) -> Range {
+    Range::new(Position::new(edit.start_line as u32 - 1, 0), line_start(text, edit.end_line as u32))
+}
+
+/// 文書に1つの変更を適用したテキストを求める
+fn apply_edit(text: &str, edit: &Edit) -> String {
+    let lines: Vec<_> = text.split_inclusive('\n').collect();
+    let start = (edit.start_line - 1).min(lines.len());
+    let end = edit.end_line.min(lines.len());
+    lines[..start].concat() + &edit.replacement + &lines[end..].concat()
+}
+
+/// 行の先頭の位置（0始まり）
+///
+/// 文書の行数を超える場合は、文書の末尾の位置に切り詰めます。
+/// 行の先頭の文字位置は常に0なので、UTF-16 への変換は不要です。
+fn line_start(text: &str, line: u32) -> Position {
+    Position::new(line, 0).min(document_end(text))
+}
+
+/// 文書の末尾の位置（最後の行の UTF-16 での長さ）
+fn document_end(text: &str) -> Position {
+    let last_line = text.rsplit('\n').next().unwrap_or_default();
+    Position::new(text.matches('\n').count() as u32, last_line.encode_utf16().count() as u32)
+}
+
+/// 2つの範囲が重なるかどうか（行単位）
+fn overlaps(a: Range, b: Range) -> bool {
+    a.start.line <= b.end.line && b.start.line < a.end.line.max(a.start.line + 1)
+}
+
+/// 文書全体を新しい内容で置き換える編集を作成する
+///
+/// 整形でレンダリング結果が変わる場合（[`sembr::verify`]）は、文書を壊さないように編集しません。
+fn replace_all(old: &str, new: String) -> Vec<TextEdit> {
+    if new == old || sembr::verify(old, &new).is_err() {
+        return Vec::new();
+    }
+
+    vec![TextEdit::new(Range::new(Position::new(0, 0), document_end(old)), new)]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use lsp_types::{
+        CodeActionContext, CodeActionOrCommand, CodeActionParams, NumberOrString, Position, Range,
+        TextDocumentIdentifier, Url,
+    };
+    use sembr::{Edit, Formatter, Options, Rule};
+
+    use super::{Document, Server, apply_edit};
+
+    /// テスト用の文書を1つ開いたサーバーを作成する
+    fn open(text: &str) -> (Server, Url) {
+        let uri = Url::parse("untitled:test.md").unwrap();
+        let document = Document {
+            text: text.to_owned(),
+            formatter: Formatter::new(Options::default()).unwrap(),
+            included: true,
+        };
+        let server = Server { config: None, documents: HashMap::from([(uri.clone(), document)]) };
+        (server, uri)
+    }
+
+    /// 診断とクイックフィックスのテスト
+    #[test]
+    fn test_diagnostics_and_code_actions() {
+        let (server, uri) = open("# title\n\nFirst. Second.\n\nshort\nline\n");
+
+        let diagnostics = server.diagnostics(&uri);
+        assert_eq!(2, diagnostics.len());
+        assert_eq!(Some(NumberOrString::String("needs-split".to_owned())), diagnostics[0].code);
+        assert_eq!(Range::new(Position::new(2, 0), Position::new(3, 0)), diagnostics[0].range);
+        assert_eq!(Some(NumberOrString::String("can-join".to_owned())), diagnostics[1].code);
+        assert_eq!(Range::new(Position::new(4, 0), Position::new(6, 0)), diagnostics[1].range);
+
+        // カーソルのある行の変更だけがクイックフィックスになる
+        let params = CodeActionParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+            range: Range::new(Position::new(2, 3), Position::new(2, 3)),
+            context: CodeActionContext { diagnostics: diagnostics.clone(), ..Default::default() },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = server.code_actions(&params);
+        assert_eq!(1, actions.len());
+        let CodeActionOrCommand::CodeAction(action) = &actions[0] else { panic!() };
+        let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        assert_eq!("First.\nSecond.\n", edits[0].new_text);
+        assert_eq!(Some(vec![diagnostics[0].clone()]), action.diagnostics);
+
+        // 整形を抑制するコメントの誤りも診断になる
+        let (server, uri) = open("text\n<!-- sembr-ignore-end -->\n");
+        let diagnostics = server.diagnostics(&uri);
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(
+            Some(NumberOrString::String("unmatched-ignore-end".to_owned())),
+            diagnostics[0].code
+        );
+        assert_eq!(Range::new(Position::new(1, 0), Position::new(2, 0)), diagnostics[0].range);
+    }
+
+    /// 整形のテスト
+    #[test]
+    fn test_formatting() {
+        let (server, uri) = open("First. Second.\n\nshort\nline\n");
+
+        let edits = server.formatting(&uri);
+        assert_eq!(1, edits.len());
+        assert_eq!(Range::new(Position::new(0, 0), Position::new(4, 0)), edits[0].range);
+        assert_eq!("First.\nSecond.\n\nshort line\n", edits[0].new_text);
+
+        // 範囲の整形は、範囲に重なる段落だけを変更する
+        let range = Range::new(Position::new(3, 0), Position::new(3, 0));
+        let edits = server.range_formatting(&uri, range);
+        assert_eq!("First. Second.\n\nshort line\n", edits[0].new_text);
+
+        // 行頭で終わる選択範囲は、その行を含まない
+        let (server, uri) = open("First. Second.\n\nThird. Fourth.\n");
+        let range = Range::new(Position::new(0, 0), Position::new(2, 0));
+        let edits = server.range_formatting(&uri, range);
+        assert_eq!("First.\nSecond.\n\nThird. Fourth.\n", edits[0].new_text);
+
+        // 適合している文書は変更しない
+        let (server, uri) = open("First.\nSecond.\n");
+        assert!(server.formatting(&uri).is_empty());
+    }
+
+    /// 末尾に改行のない文書で、変更の範囲が文書の末尾を超えないことのテスト
+    #[test]
+    fn test_edit_range_at_end_of_document() {
+        let (server, uri) = open("# title\n\nFirst. Second.");
+
+        let diagnostics = server.diagnostics(&uri);
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(Range::new(Position::new(2, 0), Position::new(2, 14)), diagnostics[0].range);
+
+        let params = CodeActionParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+            range: Range::new(Position::new(2, 0), Position::new(2, 0)),
+            context: CodeActionContext::default(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = server.code_actions(&params);
+        let CodeActionOrCommand::CodeAction(action) = &actions[0] else { panic!() };
+        let edit = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri][0];
+        assert_eq!(Range::new(Position::new(2, 0), Position::new(2, 14)), edit.range);
+        assert_eq!("First.\nSecond.", edit.new_text);
+    }
+
+    /// 変更の適用と、レンダリング結果が変わる変更の検出のテスト
+    #[test]
+    fn test_apply_edit() {
+        let text = "# title\n\nshort\nline\n";
+        let edit = |start_line, end_line, replacement: &str| Edit {
+            start_line,
+            end_line,
+            rule: Rule::CanJoin,
+            replacement: replacement.to_owned(),
+        };
+
+        let joined = apply_edit(text, &edit(3, 4, "short line\n"));
+        assert_eq!("# title\n\nshort line\n", joined);
+        assert!(sembr::verify(text, &joined).is_ok());
+
+        // 段落を見出しに変える変更は、レンダリング結果が変わる
+        let broken = apply_edit(text, &edit(3, 4, "short\n===\n"));
+        assert_eq!("# title\n\nshort\n===\n", broken);
+        assert!(sembr::verify(text, &broken).is_err());
+    }
+}
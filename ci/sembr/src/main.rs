@@ -2,31 +2,75 @@
 //!
 //! このツールは、Markdownファイルの文章を適切に分割・結合し、
 //! 読みやすさとGit差分の品質を向上させます。
+//! 整形処理そのものは `sembr` ライブラリにあり、
+//! このバイナリはファイルの走査と結果の表示だけを行います。
 //!
 //! ## 主な機能
-//! 1. 長い文を句読点（. ! ? ;）で分割
+//! 1. 長い文を句読点（. ! ? ; および全角の 。！？；）で分割
 //! 2. 短い文を結合して行長を最適化
-//! 3. コードブロックやテーブルを保護
+//! 3. CommonMark の構造に基づき、コードブロックやテーブル、見出しなどを保護
 //!
 //! ## 使用方法
 //! ```bash
 //! sembr <path> [--overwrite] [--show-diff] [--line-length-limit 100]
+//! sembr - [--lines START:END] < input.md > output.md
+//! sembr <path> --overwrite --changed-since origin/master
+//! sembr <path> --staged
+//! sembr lsp [--config sembr.toml]
 //! ```
 //!
+//! パスに `-` を指定すると、標準入力の Markdown を整形して標準出力に書き出します。
+//! エディタで保存前のバッファを整形するときに使います。
+//!
+//! `lsp` サブコマンドは、標準入出力で通信する言語サーバーとして動作し、
+//! エディタに診断、クイックフィックス、整形を提供します（`lsp` モジュールを参照）。
+//!
+//! 整形規則やチェック対象のファイルは、リポジトリのルートにある
+//! `sembr.toml` でも設定できます（[`sembr::config`] を参照）。
+//! コマンドライン引数は設定ファイルより優先されます。
+//!
 //! ## オプション
-//! - `--overwrite`: ファイルを直接変更
+//! - `--overwrite`: ファイルを直接変更（整形でレンダリング結果が変わるファイルは変更しない）
 //! - `--show-diff`: 変更内容を diff 形式で表示
 //! - `--line-length-limit`: 行長制限（デフォルト: 100）
-
-use std::path::PathBuf;
-use std::sync::LazyLock;
-use std::{fs, process};
-
-use anyhow::Result;
-use clap::Parser;
+//! - `--line-length-unit`: 行長の単位（デフォルト: columns = 表示幅）
+//! - `--cjk-latin-spacing`: CJK文字とラテン文字の境界で結合する際の空白（デフォルト: omit）
+//! - `--abbreviation`: 文末とみなさない略語を追加（複数指定可）
+//! - `--config`: 設定ファイルのパス（デフォルト: 親ディレクトリから `sembr.toml` を探索）
+//! - `--format`: チェック結果の出力形式（text, json, sarif）
+//! - `--lines`: 指定した行範囲に重なる段落だけを整形（1始まり、両端を含む）
+//! - `--max-line-length`: 長すぎる文を節の境界でも分割する最大行長（デフォルト: 制限なし）
+//! - `--no-cache`: 前回の実行で適合していたファイルも整形し直す
+//! - `--line-ending`: 改行コード（デフォルト: preserve = 元のファイルに合わせる）
+//! - `--insert-final-newline`: 末尾に改行のないファイルに改行を追加
+//! - `--strip-bom`: 先頭の BOM を取り除く
+//! - `--changed-since`: 指定したリビジョンからの変更に重なる段落だけを整形（`git` モジュールを参照）
+//! - `--staged`: ステージされた変更に重なる段落だけを、インデックスの内容でチェック
+//!
+//! ファイルは CPU のコア数に応じて並列に処理し、結果は走査の順に表示します。
+//! 前回の実行で適合していたファイルは、内容と整形規則が変わっていなければ
+//! 整形を省略します（`cache` モジュールを参照）。
+
+mod cache;
+mod git;
+mod lsp;
+mod report;
+
+use std::io::{self, Read};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::{env, fs, process};
+
+use anyhow::{Result, bail};
+use cache::Cache;
+use clap::{Parser, Subcommand};
+use git::{Base, Changes};
 use ignore::Walk;
 use imara_diff::{Algorithm, BasicLineDiffPrinter, Diff, InternedInput, UnifiedDiffConfig};
-use regex::Regex;
+use rayon::prelude::*;
+use report::{FileWarning, OutputFormat, Violation};
+use sembr::config::{Config, Rules};
+use sembr::{CjkLatinSpacing, Formatter, LengthUnit, LineEnding};
 
 /// コマンドライン引数の定義
 ///
@@ -36,14 +80,37 @@ use regex::Regex;
 /// - `path`: チェック対象のファイルまたはディレクトリ
 /// - `overwrite`: 適合しないファイルを自動修正するかどうか
 /// - `line_length_limit`: 行を結合する際の最大長
+/// - `line_length_unit`: 行の長さを数える単位
 /// - `show_diff`: 変更内容をdiff形式で表示するかどうか
+/// - `cjk_latin_spacing`: CJK/ラテン境界で行を結合する際の空白の扱い
+/// - `abbreviations`: 文末とみなさない略語の追加分
+/// - `config`: 設定ファイルのパス
+/// - `format`: チェック結果の出力形式
+/// - `lines`: 整形する行範囲
+/// - `max_line_length`: 1行の最大長
+/// - `no_cache`: 適合済みのファイルのキャッシュを使わないかどうか
+/// - `changed_since`: 変更に重なる段落だけを整形する場合の比較対象のリビジョン
+/// - `staged`: ステージされた変更に重なる段落だけをチェックするかどうか
+/// - `line_ending`: 整形結果の改行コード
+/// - `insert_final_newline`: 末尾に改行のないファイルに改行を追加するかどうか
+/// - `strip_bom`: 先頭の BOM を取り除くかどうか
+///
+/// 整形規則に関する引数は、指定された場合のみ設定ファイルの値を上書きします。
+/// サブコマンドを指定した場合、これらの引数は使えません。
 #[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Cli {
+    /// サブコマンド（省略時はファイルのチェック・整形）
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// チェック対象のファイルまたはディレクトリのパス
     ///
     /// ファイルを指定した場合はそのファイルのみ処理。
     /// ディレクトリを指定した場合は再帰的に .md ファイルを検索。
-    path: PathBuf,
+    /// `-` を指定した場合は標準入力を整形し、結果を標準出力に書き出す。
+    #[arg(required = true)]
+    path: Option<PathBuf>,
 
     /// 適合しないファイルを自動的に修正する
     ///
@@ -55,9 +122,18 @@ struct Cli {
     /// 行を結合する際の最大行長
     ///
     /// この長さ以下の場合、次の行と結合を試みます。
-    /// デフォルトは100文字。
-    #[arg(long, default_value_t = 100)]
-    line_length_limit: usize,
+    /// デフォルトは100桁。
+    /// 長さの数え方は `--line-length-unit` で指定します。
+    #[arg(long)]
+    line_length_limit: Option<usize>,
+
+    /// 行の長さを数える単位
+    ///
+    /// `columns` は端末での表示幅で、全角文字を2桁として数えます。
+    /// `chars` は Unicode スカラー値の数で、全角文字も1文字として数えます。
+    /// デフォルトは `columns`。
+    #[arg(long, value_enum)]
+    line_length_unit: Option<LengthUnit>,
 
     /// 変更内容をdiff形式で表示する
     ///
@@ -65,73 +141,173 @@ struct Cli {
     /// Unified Diff 形式で表示します。
     #[arg(long)]
     show_diff: bool,
+
+    /// CJK文字とラテン文字の境界で行を結合する際の空白の扱い
+    ///
+    /// CJK文字同士は空白なし、ラテン文字同士は空白1つで結合します。
+    /// その境界（`Rustの` + `compiler` など）に空白を入れるかどうかを指定します。
+    /// デフォルトは `omit`。
+    #[arg(long, value_enum)]
+    cjk_latin_spacing: Option<CjkLatinSpacing>,
+
+    /// 文末とみなさない略語を追加する
+    ///
+    /// 既定の略語（`e.g.`、`i.e.`、`etc.`、`vs.`、`cf.` など）に加えて、
+    /// ここで指定した略語の直後でも文を分割しなくなります。
+    /// 複数回指定できます。
+    #[arg(long = "abbreviation", value_name = "ABBREVIATION")]
+    abbreviations: Vec<String>,
+
+    /// 設定ファイルのパス
+    ///
+    /// 指定しない場合は、チェック対象のパスから親ディレクトリへ向かって
+    /// `sembr.toml` を探索します（Git リポジトリのルートまで）。
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// チェック結果の出力形式
+    ///
+    /// `json` と `sarif` は、違反ごとにファイル、行範囲、規則
+    /// （`needs-split` または `can-join`）、置き換え後のテキストを出力します。
+    /// `--overwrite` や `--show-diff` とは併用できません。
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text, conflicts_with_all = ["overwrite", "show_diff"])]
+    format: OutputFormat,
+
+    /// 指定した行範囲に重なる段落だけを整形する
+    ///
+    /// `START:END` の形式で、行番号は1始まり、両端を含みます。
+    /// 範囲外の段落は変更しません。
+    /// ファイルか `-` と組み合わせて使います。
+    #[arg(long, value_name = "START:END", value_parser = parse_line_range, conflicts_with_all = ["changed_since", "staged"])]
+    lines: Option<RangeInclusive<usize>>,
+
+    /// 1行の最大長
+    ///
+    /// これを超える文は、カンマ、コロン、ダッシュ、接続詞、`、`、`，` の位置でも分割します。
+    /// 分割しても収まらない行は警告として表示します。
+    /// 指定しない場合は制限しません。
+    #[arg(long)]
+    max_line_length: Option<usize>,
+
+    /// 適合済みのファイルのキャッシュを使わない
+    ///
+    /// 通常は、前回の実行で適合していたファイルのうち、内容、sembr のバージョン、
+    /// 整形規則が変わっていないものの整形を省略します。
+    /// キャッシュは設定ファイルのディレクトリの `.sembr-cache` に保存します。
+    /// 設定ファイルがない場合は、キャッシュを使いません。
+    /// `--lines`、`--changed-since`、`--staged` を指定した場合は常に使いません。
+    #[arg(long)]
+    no_cache: bool,
+
+    /// 指定したリビジョンからの変更に重なる段落だけを整形する
+    ///
+    /// 作業ツリーと `REV` の差分（`git diff REV`）で追加・変更された行を含む段落だけを
+    /// 分割・結合し、変更していない段落の改行位置は変えません。
+    /// 差分のないファイルと、Git が追跡していないファイルは処理しません。
+    #[arg(long, value_name = "REV", conflicts_with = "staged")]
+    changed_since: Option<String>,
+
+    /// ステージされた変更に重なる段落だけをチェックする
+    ///
+    /// コミット前のフックで使います。
+    /// ファイルの内容は作業ツリーではなくインデックスから読み込み、
+    /// `git diff --cached` で追加・変更された行を含む段落だけをチェックします。
+    /// 作業ツリーの未ステージの変更を上書きしないように、`--overwrite` とは併用できません。
+    #[arg(long, conflicts_with = "overwrite")]
+    staged: bool,
+
+    /// 整形結果の改行コード
+    ///
+    /// `preserve` は元のファイルの改行コードに合わせます
+    /// （LF と CRLF が混在しているファイルは、整形せずに警告します）。
+    /// `lf` と `crlf` は、すべての行をその改行コードに統一します。
+    /// デフォルトは `preserve`。
+    #[arg(long, value_enum)]
+    line_ending: Option<LineEnding>,
+
+    /// 末尾に改行のないファイルに改行を追加する
+    ///
+    /// 指定しない場合は、末尾の改行の有無を変えません。
+    #[arg(long)]
+    insert_final_newline: bool,
+
+    /// 先頭の BOM（U+FEFF）を取り除く
+    ///
+    /// 指定しない場合は、BOM があればそのまま残します。
+    #[arg(long)]
+    strip_bom: bool,
 }
 
-/// 文末の句読点パターンにマッチする正規表現
-///
-/// 行末が以下の文字で終わる場合、その行で文が終わっていると判断します：
-/// - `.` (ピリオド)
-/// - `?` (疑問符)
-/// - `;` (セミコロン)
-/// - `!` (感嘆符)
-/// - `,` (カンマ)
-/// - `-` (ハイフン)
-///
-/// LazyLock により、初回アクセス時に一度だけコンパイルされます。
-static REGEX_IGNORE_END: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"(\.|\?|;|!|,|\-)$").unwrap());
+/// 1つのファイルの処理結果
+enum Outcome {
+    /// 適合している（変更なし）
+    Compliant,
+    /// 自動修正した
+    MadeCompliant,
+    /// 整形するとレンダリング結果が変わるため、書き換えなかった
+    NotWritten(sembr::Error),
+    /// 変更内容（`--show-diff` の場合）
+    Diff { old: String, new: String },
+    /// 適合していない（`--format json|sarif` の場合は行単位の違反を含む）
+    NotCompliant(Vec<Violation>),
+}
 
-/// リンクターゲット定義を検出する正規表現
-///
-/// Markdown のリンク参照定義（`[label]: URL`）を検出します。
-/// この形式の行は文章ではないため、分割・結合の対象外とします。
-///
-/// # マッチ例
-/// ```markdown
-/// [RFC 2119]: https://www.rfc-editor.org/rfc/rfc2119
-/// [another link]: /path/to/doc
-/// ```
-static REGEX_IGNORE_LINK_TARGETS: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"^\[.+\]: ").unwrap());
+/// 1つのファイルを処理した結果と、表示する警告
+struct Processed {
+    /// ファイルのパス
+    path: PathBuf,
+    /// 整形を抑制するコメントの誤りなどの警告
+    warnings: Vec<FileWarning>,
+    /// 処理結果
+    outcome: Outcome,
+    /// キャッシュに記録するキー（警告なしで適合していた場合のみ）
+    cache_key: Option<String>,
+}
 
-/// 文の分割位置を検出する正規表現
-///
-/// この正規表現は、文を分割すべき位置（句読点の後）を検出します。
-///
-/// # パターンの詳細
-/// - `[^\.\d\-\*]\.`: ドット以外 + ドット（小数点や省略記号を除外）
-/// - `[^r]\?`: r以外 + ?（r? はGitコマンドなので除外）
-/// - `;`: セミコロン
-/// - `!`: 感嘆符
-/// - `\s`: 後続の空白
-///
-/// # 除外されるケース
-/// - 小数点（1.5）
-/// - 省略記号（...）
-/// - リスト記号（- や *）
-/// - Gitコマンド（r? @reviewer）
-static REGEX_SPLIT: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"([^\.\d\-\*]\.|[^r]\?|;|!)\s").unwrap());
-
-/// リスト項目を検出する正規表現
-///
-/// Markdown のリスト項目（番号付き/記号）を検出します。
-///
-/// # マッチするパターン
-/// - 番号付きリスト: `1. `, `2. `, ...
-/// - ダッシュリスト: `- `
-/// - アスタリスクリスト: `* `
-///
-/// インデントがあっても正しく検出します。
+/// サブコマンド
+#[derive(Subcommand)]
+enum Command {
+    /// 言語サーバーとして標準入出力で通信する
+    ///
+    /// 開いている Markdown 文書に診断とクイックフィックスを提供し、
+    /// `textDocument/formatting` に応じます。
+    Lsp {
+        /// 全文書に使う設定ファイルのパス
+        ///
+        /// 指定しない場合は、文書ごとにそのパスから `sembr.toml` を探索します。
+        #[arg(long)]
+        config: Option<PathBuf>,
+    },
+}
+
+/// 標準入力を表すパス
+const STDIN: &str = "-";
+
+/// `--lines` の値（`START:END`）をパースする
 ///
-/// # マッチ例
-/// ```markdown
-/// 1. First item
-///   - Nested item
-///     * More nested
+/// # 使用例
+/// ```rust,ignore
+/// assert_eq!(parse_line_range("3:7"), Ok(3..=7));
+/// assert!(parse_line_range("7:3").is_err());
 /// ```
-static REGEX_LIST_ENTRY: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"^\s*(\d\.|\-|\*)\s+").unwrap());
+fn parse_line_range(value: &str) -> Result<RangeInclusive<usize>, String> {
+    let parse = |number: &str| {
+        number
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .filter(|&number| number > 0)
+            .ok_or_else(|| format!("invalid line number `{number}`"))
+    };
+
+    let (start, end) =
+        value.split_once(':').ok_or_else(|| format!("expected START:END, found `{value}`"))?;
+    let (start, end) = (parse(start)?, parse(end)?);
+    if start > end {
+        return Err(format!("start line {start} is after end line {end}"));
+    }
+    Ok(start..=end)
+}
 
 /// メイン関数
 ///
@@ -147,11 +323,98 @@ static REGEX_LIST_ENTRY: LazyLock<Regex> =
 ///
 /// # 終了コード
 /// - 0: 全てのファイルが適合（または修正完了）
-/// - 1: 非適合ファイルが存在
+/// - 1: 非適合ファイルが存在、または整形でレンダリング結果が変わるファイルが存在
 fn main() -> Result<()> {
     // コマンドライン引数をパース
     let cli = Cli::parse();
 
+    // 言語サーバーモード
+    if let Some(Command::Lsp { config }) = &cli.command {
+        let config = config.as_deref().map(Config::load).transpose()?;
+        return lsp::run(config);
+    }
+
+    // サブコマンドがなければ、パスは必須（clap が保証する）
+    let path = cli.path.as_deref().expect("path is required without a subcommand");
+
+    // 標準入力を整形するかどうか
+    let stdin = path.as_os_str() == STDIN;
+
+    // 行範囲の指定は、単一のファイルか標準入力に対してのみ意味を持つ
+    if cli.lines.is_some() && path.is_dir() {
+        bail!("`--lines` requires a single file or `{STDIN}`");
+    }
+
+    // Git の差分は、リポジトリ内のファイルに対してのみ求められる
+    let base = match (&cli.changed_since, cli.staged) {
+        (Some(revision), _) => Some(Base::Revision(revision.clone())),
+        (None, true) => Some(Base::Index),
+        (None, false) => None,
+    };
+    if base.is_some() && stdin {
+        bail!("`--changed-since` and `--staged` cannot be used with `{STDIN}`");
+    }
+    let changes = base.map(|base| Changes::load(path, base)).transpose()?;
+
+    // 設定ファイルを読み込む（指定がなければ探索）
+    // 標準入力の場合は、カレントディレクトリから探索する
+    let config = match &cli.config {
+        Some(path) => Config::load(path)?,
+        None if stdin => Config::discover(&env::current_dir()?)?,
+        None => Config::discover(path)?,
+    };
+
+    // コマンドライン引数で指定された整形規則（設定ファイルより優先）
+    let cli_rules = Rules {
+        line_length_limit: cli.line_length_limit,
+        line_length_unit: cli.line_length_unit,
+        cjk_latin_spacing: cli.cjk_latin_spacing,
+        max_line_length: cli.max_line_length,
+        line_ending: cli.line_ending,
+        insert_final_newline: cli.insert_final_newline.then_some(true),
+        strip_bom: cli.strip_bom.then_some(true),
+        ..Rules::default()
+    };
+
+    // ファイルに適用する整形器を作成
+    // `--abbreviation` は設定ファイルの略語に追加する
+    let formatter_for = |path: &Path| -> Result<Formatter> {
+        let mut options = config.options_for(path, &cli_rules);
+        options.abbreviations.extend(cli.abbreviations.iter().cloned());
+        Ok(Formatter::new(options)?)
+    };
+
+    // 文章を整形（行範囲があれば、そのいずれかに重なる段落だけ）
+    let format =
+        |formatter: &Formatter, content: &str, ranges: Option<&[RangeInclusive<usize>]>| {
+            match ranges {
+                Some(ranges) => formatter.format_ranges(content, ranges),
+                None => formatter.format_str(content),
+            }
+        };
+
+    // `--lines` で指定した行範囲
+    let lines: Option<Vec<_>> = cli.lines.clone().map(|lines| vec![lines]);
+
+    // 標準入力モード: 整形結果を標準出力に書き出して終了
+    if stdin {
+        let mut old = String::new();
+        io::stdin().read_to_string(&mut old)?;
+        let formatter = formatter_for(path)?;
+        for warning in FileWarning::from_warnings(Path::new("<stdin>"), formatter.warnings(&old)) {
+            eprintln!("{warning}");
+        }
+        // レンダリング結果が変わる場合は、元の内容をそのまま書き出す
+        let new = format(&formatter, &old, lines.as_deref());
+        if let Err(error) = sembr::verify(&old, &new) {
+            eprintln!("error: <stdin>: {error}");
+            print!("{old}");
+            process::exit(1);
+        }
+        print!("{new}");
+        return Ok(());
+    }
+
     // 結果を格納するベクタ
     // 適合しているファイル
     let mut compliant = Vec::new();
@@ -159,10 +422,27 @@ fn main() -> Result<()> {
     let mut not_compliant = Vec::new();
     // 自動修正したファイル
     let mut made_compliant = Vec::new();
+    // 整形するとレンダリング結果が変わるため、書き換えなかったファイル
+    let mut not_written = Vec::new();
+    // 行単位の違反（`--format json|sarif` の場合のみ）
+    let mut violations: Vec<Violation> = Vec::new();
+    // 整形を抑制するコメントの誤り
+    let mut warnings: Vec<FileWarning> = Vec::new();
+
+    // 適合済みのファイルのキャッシュ
+    // 行範囲を指定した場合の結果は範囲ごとに異なるため、キャッシュを使わない
+    // 設定ファイルがなければ、実行したディレクトリを汚さないようにキャッシュを使わない
+    let mut cache = config
+        .root()
+        .filter(|_| !cli.no_cache && cli.lines.is_none() && changes.is_none())
+        .map(Cache::load);
+    let use_cache = cache.is_some();
 
     // ignore クレートを使用してファイルを走査
     // .gitignore などの設定を自動的に尊重
-    for result in Walk::new(cli.path) {
+    // 各ファイルには、整形する行範囲（なければファイル全体）を対応付ける
+    let mut paths: Vec<(PathBuf, Option<Vec<RangeInclusive<usize>>>)> = Vec::new();
+    for result in Walk::new(path) {
         let entry = result?;
 
         // ディレクトリはスキップ
@@ -170,44 +450,146 @@ fn main() -> Result<()> {
             continue;
         }
 
-        // パスを取得
+        // .md ファイルのうち、設定ファイルの include/exclude に従って対象とするものを処理
         let path = entry.into_path();
+        let is_markdown = path.extension().is_some_and(|extension| extension == "md");
+        if !is_markdown || !config.is_included(&path) {
+            continue;
+        }
+
+        // Git の差分があれば、変更されたファイルだけを処理
+        let ranges = match &changes {
+            Some(changes) => match changes.ranges(&path) {
+                Some(ranges) => Some(ranges.to_vec()),
+                None => continue,
+            },
+            None => lines.clone(),
+        };
+        paths.push((path, ranges));
+    }
+
+    // 1つのファイルを処理する
+    let process =
+        |(path, ranges): (PathBuf, Option<Vec<RangeInclusive<usize>>>)| -> Result<Processed> {
+            // このファイルに適用する整形器
+            let formatter = formatter_for(&path)?;
+
+            // ファイルを読み込み（`--staged` ではインデックスから）
+            let old = match &changes {
+                Some(changes) => changes.read(&path)?,
+                None => fs::read_to_string(&path)?,
+            };
 
-        // 拡張子をチェック
-        if let Some(extension) = path.extension() {
-            // .md ファイルのみ処理
-            if extension != "md" {
-                continue;
+            // 前回の実行から内容も整形規則も変わっていなければ、整形を省略
+            let key = use_cache.then(|| cache::key(&old, formatter.options()));
+            if let (Some(cache), Some(cached)) = (&cache, &key)
+                && cache.is_compliant(&path, cached)
+            {
+                return Ok(Processed {
+                    path,
+                    warnings: Vec::new(),
+                    outcome: Outcome::Compliant,
+                    cache_key: key,
+                });
             }
 
-            // ファイルを読み込み
-            let old = fs::read_to_string(&path)?;
+            // 整形を抑制するコメントの誤りは、整形結果とは関係なく警告する
+            let warnings: Vec<_> =
+                FileWarning::from_warnings(&path, formatter.warnings(&old)).collect();
 
-            // 文章を整形
-            // 1. comply: 文を分割
-            // 2. lengthen_lines: 短い文を結合
-            let new = lengthen_lines(&comply(&old), cli.line_length_limit);
+            // 文章を整形（文を分割し、短い文を結合）
+            let new = format(&formatter, &old, ranges.as_deref());
 
             // 元の内容と比較
-            if new == old {
+            let outcome = if new == old {
                 // 変更なし = 適合している
-                compliant.push(path.clone());
+                Outcome::Compliant
             } else if cli.overwrite {
-                // 自動修正モード: ファイルを上書き
-                fs::write(&path, new)?;
-                made_compliant.push(path.clone());
+                // 自動修正モード: レンダリング結果が変わらないことを確かめてから上書き
+                match sembr::verify(&old, &new) {
+                    Ok(()) => {
+                        fs::write(&path, new)?;
+                        Outcome::MadeCompliant
+                    }
+                    Err(error) => Outcome::NotWritten(error),
+                }
             } else if cli.show_diff {
                 // diff表示モード: 変更内容を表示
+                Outcome::Diff { old, new }
+            } else if cli.format != OutputFormat::Text {
+                // 行範囲があれば、そのいずれかに重なる変更だけを報告
+                let edits = formatter.check_str(&old).into_iter().filter(|edit| {
+                    ranges.as_ref().is_none_or(|ranges| {
+                        ranges.iter().any(|lines| {
+                            edit.start_line <= *lines.end() && *lines.start() <= edit.end_line
+                        })
+                    })
+                });
+                Outcome::NotCompliant(Violation::from_edits(&path, edits.collect()).collect())
+            } else {
+                // デフォルト: 非適合として記録
+                Outcome::NotCompliant(Vec::new())
+            };
+
+            // 警告なしで適合していたファイルだけをキャッシュに記録
+            let cache_key =
+                key.filter(|_| matches!(outcome, Outcome::Compliant) && warnings.is_empty());
+            Ok(Processed { path, warnings, outcome, cache_key })
+        };
+
+    // 各ファイルを並列に処理（結果は走査の順に並ぶ）
+    let processed: Vec<Processed> = paths.into_par_iter().map(process).collect::<Result<_>>()?;
+
+    // 走査の順に、結果を表示して集計
+    for Processed { path, warnings: file_warnings, outcome, cache_key } in processed {
+        for warning in file_warnings {
+            eprintln!("{warning}");
+            warnings.push(warning);
+        }
+        if let Some(cache) = &mut cache {
+            cache.update(&path, cache_key);
+        }
+
+        match outcome {
+            Outcome::Compliant => compliant.push(path),
+            Outcome::MadeCompliant => made_compliant.push(path),
+            Outcome::NotWritten(error) => {
+                eprintln!("error: {}: {error}", path.display());
+                not_written.push(path);
+            }
+            Outcome::Diff { old, new } => {
                 println!("{}:", path.display());
                 show_diff(&old, &new);
                 println!("---");
-            } else {
-                // デフォルト: 非適合として記録
-                not_compliant.push(path.clone());
             }
+            Outcome::NotCompliant(file_violations) => {
+                not_compliant.push(path);
+                violations.extend(file_violations);
+            }
+        }
+    }
+
+    // キャッシュの保存に失敗しても、チェックの結果には影響しない
+    if let Some(cache) = &mut cache {
+        cache.prune(path);
+        if let Err(error) = cache.save() {
+            eprintln!("warning: {}: {error}", cache.path().display());
         }
     }
 
+    // 機械可読な形式では、違反の一覧だけを出力
+    match cli.format {
+        OutputFormat::Text => {}
+        OutputFormat::Json => println!("{}", report::to_json(&violations)),
+        OutputFormat::Sarif => println!("{}", report::to_sarif(&violations, &warnings)),
+    }
+    if cli.format != OutputFormat::Text {
+        if !not_compliant.is_empty() || !not_written.is_empty() {
+            process::exit(1);
+        }
+        return Ok(());
+    }
+
     // 結果を表示
     if !compliant.is_empty() {
         display("compliant", &compliant);
@@ -215,6 +597,11 @@ fn main() -> Result<()> {
     if !made_compliant.is_empty() {
         display("made compliant", &made_compliant);
     }
+    if !not_written.is_empty() {
+        // 整形の不具合なので、終了コード1で知らせる
+        display("not written (formatting would change the rendered document)", &not_written);
+        process::exit(1);
+    }
     if !not_compliant.is_empty() {
         // 非適合ファイルがある場合、表示して終了コード1で終了
         display("not compliant", &not_compliant);
@@ -281,471 +668,19 @@ fn display(header: &str, paths: &[PathBuf]) {
     }
 }
 
-/// 行を無視すべきかどうかを判定
-///
-/// 以下の条件のいずれかに該当する行は、
-/// 文の分割・結合の対象外とします。
-///
-/// # 無視する行
-/// - コードブロック内（\`\`\`で囲まれた部分）
-/// - "e.g." を含む行（例示の略語）
-/// - "i.e." を含む行（言い換えの略語）
-/// - パイプ文字を含む行（テーブル）
-/// - 引用ブロック（> で始まる行）
-/// - 見出し（# で始まる行）
-/// - 空行
-/// - リンクターゲット定義（[label]: URL）
-///
-/// # 引数
-/// - `line`: チェック対象の行
-/// - `in_code_block`: 現在コードブロック内かどうか
-///
-/// # 戻り値
-/// 無視すべき場合は `true`
-///
-/// # 使用例
-/// ```rust,ignore
-/// assert!(ignore("```rust", false));
-/// assert!(ignore("| col1 | col2 |", false));
-/// assert!(!ignore("This is a normal sentence.", false));
-/// ```
-fn ignore(line: &str, in_code_block: bool) -> bool {
-    // コードブロック内は常に無視
-    in_code_block
-        // e.g. (exempli gratia: ラテン語で「例えば」)
-        || line.to_lowercase().contains("e.g.")
-        // i.e. (id est: ラテン語で「すなわち」)
-        || line.contains("i.e.")
-        // パイプはMarkdownテーブルの区切り文字
-        || line.contains('|')
-        // 引用ブロック（> で始まる、前に空白があってもよい）
-        || line.trim_start().starts_with('>')
-        // 見出し（# で始まる）
-        || line.starts_with('#')
-        // 空行（空白のみの行も含む）
-        || line.trim().is_empty()
-        // リンクターゲット定義（[label]: URL 形式）
-        || REGEX_IGNORE_LINK_TARGETS.is_match(line)
-}
-
-/// 文章を規則に適合させる（文を分割する）
-///
-/// 長い文を句読点の位置で適切に分割します。
-/// これにより、Git差分が見やすくなり、レビューが容易になります。
-///
-/// # 引数
-/// - `content`: 処理対象のMarkdownテキスト
-///
-/// # 戻り値
-/// 分割後のテキスト
-///
-/// # 処理の詳細
-/// 1. 各行を走査
-/// 2. 無視すべき行（コードブロック、テーブルなど）をスキップ
-/// 3. 句読点パターン（REGEX_SPLIT）にマッチする位置で分割
-/// 4. 分割後の各部分に適切なインデントを付与
-/// 5. 元の行を分割後の複数行で置き換え
-///
-/// # インデント処理
-/// - リスト項目の場合: リストマーカーの長さ分インデント
-/// - 通常の行の場合: 行頭の空白の数だけインデント
-///
-/// # 使用例
-/// ```rust,ignore
-/// let text = "First sentence. Second sentence.\n";
-/// let result = comply(text);
-/// // "First sentence.\nSecond sentence.\n"
-/// ```
-fn comply(content: &str) -> String {
-    // 行をベクタに変換（所有権を持つ）
-    let content: Vec<_> = content.lines().map(std::borrow::ToOwned::to_owned).collect();
-
-    // 新しい内容を格納するベクタ（初期値は元の内容）
-    let mut new_content = content.clone();
-
-    // 新しいベクタ内の現在位置
-    let mut new_n = 0;
-
-    // コードブロック内かどうかのフラグ
-    let mut in_code_block = false;
-
-    // 各行を処理
-    for (n, line) in content.into_iter().enumerate() {
-        // 最初の行以外では、インデックスを進める
-        if n != 0 {
-            new_n += 1;
-        }
-
-        // コードブロックの開始/終了を検出
-        // ``` で始まる行はコードブロックの境界
-        if line.trim_start().starts_with("```") {
-            in_code_block = !in_code_block;
-            continue;
-        }
-
-        // 無視すべき行はスキップ
-        if ignore(&line, in_code_block) {
-            continue;
-        }
-
-        // 分割パターンにマッチするかチェック
-        if REGEX_SPLIT.is_match(&line) {
-            // インデント幅を計算
-            let indent = if let Some(regex_match) = REGEX_LIST_ENTRY.find(&line) {
-                // リスト項目の場合: マーカーの長さ
-                // 例: "1. " の長さは 3
-                // 例: "  - " の長さは 4
-                regex_match.len()
-            } else {
-                // 通常の行: 最初の非空白文字の位置
-                line.find(|ch: char| !ch.is_whitespace()).unwrap()
-            };
-
-            // 正規表現で分割（区切り文字を含む）
-            let mut newly_split_lines = line.split_inclusive(&*REGEX_SPLIT);
-
-            // 最初の部分を取得（末尾の空白を削除）
-            let first = newly_split_lines.next().unwrap().trim_end().to_owned();
-
-            // 残りの部分を処理（各部分にインデントを追加）
-            let mut remaining: Vec<_> = newly_split_lines
-                .map(|portion| {
-                    // format! でインデントを追加
-                    // {:indent$} は indent 個の空白を生成
-                    format!("{:indent$}{}", "", portion.trim_end())
-                })
-                .collect();
-
-            // 分割後の行を結合
-            let mut new_lines = Vec::new();
-            new_lines.push(first);
-            new_lines.append(&mut remaining);
-
-            // 元の行を分割後の行で置き換え
-            // splice は範囲を指定して要素を置き換える
-            new_content.splice(new_n..=new_n, new_lines.clone());
-
-            // インデックスを分割後の行数分進める
-            new_n += new_lines.len() - 1;
-        }
-    }
-
-    // 行を改行で結合し、末尾に改行を追加
-    new_content.join("\n") + "\n"
-}
-
-/// 短い行を結合する
-///
-/// 分割された短い行を結合して、行長を最適化します。
-/// これにより、読みやすさを保ちながらファイルサイズを削減できます。
-///
-/// # 引数
-/// - `content`: 処理対象のテキスト
-/// - `limit`: 行長の上限（これ以下なら結合を試みる）
-///
-/// # 戻り値
-/// 結合後のテキスト
-///
-/// # 結合しない条件
-/// - コードブロック内
-/// - HTMLの div タグ内
-/// - 分割パターンにマッチする行
-/// - 次の行が無視すべき行
-/// - 次の行がリスト項目
-/// - 現在の行が句読点で終わる
-///
-/// # 処理の詳細
-/// 1. 各行をチェック
-/// 2. 結合可能かを判定
-/// 3. 可能なら次の行と結合
-/// 4. 次の行は削除
-///
-/// # 使用例
-/// ```rust,ignore
-/// let text = "Short line.\nAnother short line.\n";
-/// let result = lengthen_lines(text, 100);
-/// // "Short line. Another short line.\n"
-/// ```
-fn lengthen_lines(content: &str, limit: usize) -> String {
-    // 行をベクタに変換
-    let content: Vec<_> = content.lines().map(std::borrow::ToOwned::to_owned).collect();
-
-    // 新しい内容を格納するベクタ
-    let mut new_content = content.clone();
-
-    // 現在の位置
-    let mut new_n = 0;
-
-    // 状態フラグ
-    let mut in_code_block = false;
-    let mut in_html_div = false;
-    let mut skip_next = false;
-
-    // 各行を処理
-    for (n, line) in content.iter().enumerate() {
-        // 前回の処理で結合済みの行はスキップ
-        if skip_next {
-            skip_next = false;
-            continue;
-        }
-
-        // インデックスを進める
-        if n != 0 {
-            new_n += 1;
-        }
-
-        // コードブロックの検出
-        if line.trim_start().starts_with("```") {
-            in_code_block = !in_code_block;
-            continue;
-        }
-
-        // HTMLの div 開始タグを検出
-        if line.trim_start().starts_with("<div") {
-            in_html_div = true;
-            continue;
-        }
-
-        // HTMLの div 終了タグを検出
-        if line.trim_start().starts_with("</div") {
-            in_html_div = false;
-            continue;
-        }
-
-        // div 内はスキップ
-        if in_html_div {
-            continue;
-        }
-
-        // 無視すべき行、または分割パターンにマッチする行はスキップ
-        if ignore(line, in_code_block) || REGEX_SPLIT.is_match(line) {
-            continue;
-        }
-
-        // 次の行を取得
-        let Some(next_line) = content.get(n + 1) else {
-            // 次の行がなければ終了
-            continue;
-        };
-
-        // 次の行が結合不可能な条件をチェック
-        if ignore(next_line, in_code_block)
-            || REGEX_LIST_ENTRY.is_match(next_line)
-            || REGEX_IGNORE_END.is_match(line)
-        {
-            continue;
-        }
-
-        // 結合後の長さをチェック
-        if line.len() + next_line.len() < limit {
-            // 2つの行を結合（間にスペースを挿入）
-            // next_line.trim_start() で次の行の先頭空白を除去
-            new_content[new_n] = format!("{line} {}", next_line.trim_start());
-
-            // 次の行を削除
-            new_content.remove(new_n + 1);
-
-            // 次のイテレーションで削除した行をスキップ
-            skip_next = true;
-        }
-    }
-
-    // 行を改行で結合し、末尾に改行を追加
-    new_content.join("\n") + "\n"
-}
-
-// テストモジュール
 #[cfg(test)]
 mod tests {
-    /// sembr（文の分割）機能のテスト
-    ///
-    /// 様々な形式の文を正しく分割できることを検証します。
-    #[test]
-    fn test_sembr() {
-        // テスト用の入力テキスト
-        let original = "
-# some. heading
-must! be; split?
-1. ignore a dot after number. but no further
-ignore | tables
-ignore e.g. and
-ignore i.e. and
-ignore E.g. too
-- list. entry
- * list. entry
-```
-some code. block
-```
-sentence with *italics* should not be ignored. truly.
-git log main.. compiler
- foo.   bar.  baz
-";
-
-        // 期待される出力
-        let expected = "
-# some. heading
-must!
-be;
-split?
-1. ignore a dot after number.
-   but no further
-ignore | tables
-ignore e.g. and
-ignore i.e. and
-ignore E.g. too
-- list.
-  entry
- * list.
-   entry
-```
-some code. block
-```
-sentence with *italics* should not be ignored.
-truly.
-git log main.. compiler
- foo.
-   bar.
-  baz
-";
-
-        // comply 関数をテスト
-        assert_eq!(expected, super::comply(original));
-    }
+    use super::parse_line_range;
 
-    /// prettify（文の結合）機能のテスト
-    ///
-    /// 短い文を適切に結合し、特殊な構造（リスト、div）を
-    /// 保護できることを検証します。
-    #[test]
-    fn test_prettify() {
-        let original = "\
-do not split
-short sentences
-<div class='warning'>
-a bit of text inside
-</div>
-preserve next line
-1. one
-
-preserve next line
-- two
-
-preserve next line
-* three
-";
-
-        let expected = "\
-do not split short sentences
-<div class='warning'>
-a bit of text inside
-</div>
-preserve next line
-1. one
-
-preserve next line
-- two
-
-preserve next line
-* three
-";
-
-        assert_eq!(expected, super::lengthen_lines(original, 50));
-    }
-
-    /// インデント付き行の結合テスト
-    ///
-    /// インデントがある行も正しく結合できることを検証します。
-    #[test]
-    fn test_prettify_prefix_spaces() {
-        let original = "\
- do not split
- short sentences
-";
-
-        let expected = "\
- do not split short sentences
-";
-
-        assert_eq!(expected, super::lengthen_lines(original, 50));
-    }
-
-    /// リンクターゲットの保護テスト
-    ///
-    /// リンクターゲット定義が結合されないことを検証します。
-    #[test]
-    fn test_prettify_ignore_link_targets() {
-        let original = "\
-[a target]: https://example.com
-[another target]: https://example.com
-";
-
-        // リンクターゲットは結合されないため、変更なし
-        assert_eq!(original, super::lengthen_lines(original, 100));
-    }
-
-    /// 分割と結合の統合テスト
-    ///
-    /// comply と lengthen_lines を連続して適用した場合の
-    /// 動作を検証します。
-    #[test]
-    fn test_sembr_then_prettify() {
-        let original = "
-hi there. do
-not split
-short sentences.
-hi again.
-";
-
-        // 1回目の処理: 分割
-        let expected = "
-hi there.
-do
-not split
-short sentences.
-hi again.
-";
-        let processed = super::comply(original);
-        assert_eq!(expected, processed);
-
-        // 2回目の処理: 結合（制限50）
-        let expected = "
-hi there.
-do not split
-short sentences.
-hi again.
-";
-        let processed = super::lengthen_lines(&processed, 50);
-        assert_eq!(expected, processed);
-
-        // 3回目の処理: さらに結合
-        let expected = "
-hi there.
-do not split short sentences.
-hi again.
-";
-        let processed = super::lengthen_lines(&processed, 50);
-        assert_eq!(expected, processed);
-    }
-
-    /// 疑問符の処理テスト
-    ///
-    /// 疑問符（?）の特殊ケースを正しく処理できることを検証します。
-    /// 特に "r?" (Gitのレビュー依頼) は分割しないようにします。
+    /// `--lines` の値のパースのテスト
     #[test]
-    fn test_sembr_question_mark() {
-        let original = "
-o? whatever
-r? @reviewer
- r? @reviewer
-";
-
-        let expected = "
-o?
-whatever
-r? @reviewer
- r? @reviewer
-";
-
-        assert_eq!(expected, super::comply(original));
+    fn test_parse_line_range() {
+        assert_eq!(Ok(3..=7), parse_line_range("3:7"));
+        assert_eq!(Ok(5..=5), parse_line_range("5:5"));
+
+        assert!(parse_line_range("7:3").is_err());
+        assert!(parse_line_range("0:3").is_err());
+        assert!(parse_line_range("3").is_err());
+        assert!(parse_line_range("a:b").is_err());
     }
 }
@@ -0,0 +1,1049 @@
+//! CommonMark のイベント列に基づく文書構造の解析
+//!
+//! 行ごとの正規表現やフラグで状態を追跡する代わりに、
+//! `pulldown-cmark` のイベント列から各行の役割を求めます。
+//! 分割・結合の候補になるのは段落のテキストノードだけで、
+//! コードブロック、見出し、テーブル、HTMLブロック、リンク参照定義、
+//! インラインコード、リンク先 URL などは構造的に保護されます。
+//!
+//! リンク・画像・強調・自動リンクの内側のテキストも、
+//! 途中で改行すると読みにくくなるため、分割しない一つの単位として扱います。
+//!
+//! 引用ブロックやリスト項目の内側の段落も対象です。
+//! 各行の先頭のコンテナの記号（`> `、`- ` など）は [`Document::prefix`] で求め、
+//! 分割で追加する行には [`continuation`] の接頭辞を付けます。
+//!
+//! mdBook 固有の構文（`{{#include ...}}` などの指令、KaTeX の `$$` 数式ブロック）は
+//! CommonMark では段落のテキストになりますが、分割・結合しない一つの単位として扱います。
+//! mermaid や admonish のブロックはコードフェンスなので、構造的に保護されます。
+//!
+//! 整形を抑制するコメント（`<!-- sembr-ignore -->` など、[`crate::suppress`] を参照）が
+//! 指定したブロックや行の範囲も、分割・結合の対象外です。
+//!
+//! HTML ブロックで開かれた要素（`<div class="warning">`、`<details>` など）の内側は、
+//! 空行を挟んだ Markdown の段落であっても分割・結合の対象外とします（[`HtmlElements`]）。
+
+use std::ops::Range;
+use std::sync::LazyLock;
+
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+use regex::Regex;
+
+use crate::suppress::Suppression;
+use crate::warning::{Warning, WarningKind};
+
+/// mdBook と同じ拡張機能を有効にしたパーサーの設定
+///
+/// テーブルや脚注を認識しないと、それらが段落として扱われてしまうため、
+/// ガイドをレンダリングする mdBook と同じ拡張を有効にします。
+///
+/// テーブルは GFM と同じく、ヘッダー行と区切り行の組で始まる場合だけ認識されます。
+/// クロージャ（`|x| x + 1`）やシェルのパイプなど、`|` を含むだけの文章は段落のままです。
+pub fn parser_options() -> Options {
+    Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_HEADING_ATTRIBUTES
+}
+
+/// 1行分の解析結果
+#[derive(Clone, Debug, Default)]
+struct LineInfo {
+    /// 分割候補となる段落テキストの範囲（行頭からのバイトオフセット）
+    text: Vec<Range<usize>>,
+
+    /// 行末の改行が段落内のソフト改行である
+    ///
+    /// ハード改行（行末の2つの空白や `\`）や段落の終わりでは `false` です。
+    soft_break: bool,
+
+    /// 行頭のコンテナの接頭辞（`> `、`- `、継続行のインデントなど）のバイト数
+    prefix: usize,
+
+    /// 段落の内容が始まる行である
+    ///
+    /// 強調やインラインコードだけの行のように、分割候補のテキストがない行も含みます。
+    paragraph: bool,
+}
+
+impl LineInfo {
+    /// 範囲をテキストから取り除く
+    ///
+    /// 範囲と重なるテキストの範囲は、重ならない前後の部分だけを残します。
+    fn remove_text(&mut self, removed: &Range<usize>) {
+        self.text = self
+            .text
+            .iter()
+            .flat_map(|range| {
+                [range.start..range.end.min(removed.start), range.start.max(removed.end)..range.end]
+            })
+            .filter(|range| !range.is_empty())
+            .collect();
+    }
+}
+
+/// 解析済みの Markdown 文書
+///
+/// 行番号（0始まり）をキーとして、各行が分割・結合の候補かどうかを答えます。
+pub struct Document {
+    /// 各行の解析結果
+    lines: Vec<LineInfo>,
+
+    /// 整形を抑制するコメントについての警告
+    warnings: Vec<Warning>,
+}
+
+impl Document {
+    /// Markdown テキストを解析する
+    ///
+    /// # 処理の詳細
+    /// 1. 各行の先頭のバイトオフセットを求める
+    /// 2. イベント列を走査し、現在のブロックの入れ子をスタックで追跡
+    /// 3. 段落（または tight なリスト項目）直下のテキストを分割候補として記録
+    /// 4. 同じ文脈のソフト改行を結合候補として記録
+    /// 5. 各行で段落の内容が始まる位置から、コンテナの接頭辞の長さを求める
+    /// 6. mdBook 固有の構文を対象外にする（[`Document::protect_mdbook`]）
+    /// 7. 整形を抑制するコメントが指定した行を対象外にする
+    pub fn parse(content: &str) -> Self {
+        // 各行の先頭のバイトオフセット
+        let line_starts: Vec<usize> = std::iter::once(0)
+            .chain(content.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+        let mut lines = vec![LineInfo::default(); line_starts.len()];
+
+        // バイトオフセットから行番号を求める
+        let line_of = |offset: usize| line_starts.partition_point(|&start| start <= offset) - 1;
+
+        // 現在開いているブロック要素のスタック
+        let mut blocks = Vec::new();
+        // 現在開いている、分割しないインライン要素（リンク・強調など）の数
+        let mut atomic_inlines = 0_usize;
+        // HTML ブロックで開かれたまま閉じられていない要素
+        let mut html = HtmlElements::default();
+        // 段落内のインライン HTML で開かれている要素（`<span>x. y</span>` の内側は分割しない）
+        let mut inline_html = HtmlElements::default();
+
+        // 各行で最初に段落の内容が始まるバイトオフセット
+        let mut content_starts = vec![None; line_starts.len()];
+
+        // 整形を抑制するコメントで対象外にする行の範囲
+        let mut suppressed = Vec::new();
+        // `sembr-ignore` の直後で、次のブロックを対象外にするかどうか
+        let mut suppress_next = false;
+        // 閉じられていない `sembr-ignore-start` の行
+        let mut suppress_start = None;
+        let mut warnings = Vec::new();
+
+        for (event, range) in Parser::new_ext(content, parser_options()).into_offset_iter() {
+            // 段落の開始とインラインのイベントは、行内の内容の位置を表す
+            // リスト項目などのブロックの開始は、コンテナの記号の位置なので除外
+            let is_content = match &event {
+                Event::Start(tag) => !is_block(tag) || matches!(tag, Tag::Paragraph),
+                Event::End(_) => false,
+                _ => true,
+            };
+
+            match event {
+                Event::Start(tag) if is_block(&tag) => {
+                    if std::mem::take(&mut suppress_next) {
+                        let end = line_of(range.end.max(range.start + 1) - 1);
+                        suppressed.push(line_of(range.start)..end + 1);
+                    }
+                    blocks.push(tag);
+                }
+                Event::End(tag) if is_block_end(tag) => {
+                    blocks.pop();
+                    // インライン HTML の要素は、閉じられていなくても段落の終わりまで
+                    inline_html = HtmlElements::default();
+                }
+                Event::Start(_) => atomic_inlines += 1,
+                Event::End(_) => atomic_inlines -= 1,
+                Event::InlineHtml(text) => inline_html.update(&text),
+                Event::Text(_)
+                    if is_prose(&blocks)
+                        && atomic_inlines == 0
+                        && !html.is_inside()
+                        && !inline_html.is_inside() =>
+                {
+                    // テキストが複数行にまたがる場合に備えて、行ごとに記録
+                    for n in line_of(range.start)..=line_of(range.end - 1) {
+                        let start = range.start.max(line_starts[n]);
+                        let end =
+                            line_starts.get(n + 1).map_or(range.end, |&next| range.end.min(next));
+                        lines[n].text.push(start - line_starts[n]..end - line_starts[n]);
+                    }
+                }
+                Event::SoftBreak if is_prose(&blocks) && !html.is_inside() => {
+                    lines[line_of(range.start)].soft_break = true;
+                }
+                // HTML ブロックの内容から、開いている要素と整形を抑制するコメントを追跡
+                // 段落内のインライン HTML（`Event::InlineHtml`）は上で別に追跡する
+                Event::Html(text) => {
+                    html.update(&text);
+
+                    let n = line_of(range.start);
+                    match Suppression::parse(&text) {
+                        Some(Suppression::File) => suppressed.push(0..line_starts.len()),
+                        Some(Suppression::Next) => suppress_next = true,
+                        Some(Suppression::Start) => {
+                            suppress_start.get_or_insert(n);
+                        }
+                        Some(Suppression::End) => match suppress_start.take() {
+                            Some(start) => suppressed.push(start..n + 1),
+                            None => warnings.push(Warning {
+                                line: n + 1,
+                                kind: WarningKind::UnmatchedIgnoreEnd,
+                            }),
+                        },
+                        None => {}
+                    }
+                }
+                _ => {}
+            }
+
+            if is_content && is_prose(&blocks) {
+                let n = line_of(range.start);
+                let start = range.start - line_starts[n];
+                content_starts[n] =
+                    Some(content_starts[n].map_or(start, |old: usize| old.min(start)));
+            }
+        }
+
+        // 行頭のコンテナの記号の範囲と、内容の開始位置の短い方を接頭辞とする
+        // 前の行から続くインラインコードなどで内容の開始位置が行の途中になる場合や、
+        // 内容がリストの記号のように見える場合（`2019. was` など）にも正しく求められる
+        for (n, info) in lines.iter_mut().enumerate() {
+            let end = line_starts.get(n + 1).map_or(content.len(), |&next| next - 1);
+            let prefix = REGEX_CONTAINER_PREFIX.find(&content[line_starts[n]..end]).unwrap().end();
+            info.prefix = content_starts[n].map_or(prefix, |start| start.min(prefix));
+            info.paragraph = content_starts[n].is_some();
+        }
+
+        // 閉じられていない領域は、ファイルの終わりまでとする
+        if let Some(start) = suppress_start {
+            warnings.push(Warning { line: start + 1, kind: WarningKind::UnterminatedIgnore });
+            suppressed.push(start..line_starts.len());
+        }
+
+        let mut document = Self { lines, warnings };
+        document.protect_mdbook(content, &line_starts);
+        for line in suppressed.into_iter().flatten() {
+            document.protect_line(line);
+        }
+        document
+    }
+
+    /// 整形を抑制するコメントについての警告
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// mdBook 固有の構文を分割・結合の対象外にする
+    ///
+    /// これらは CommonMark では段落のテキストですが、
+    /// mdBook のプリプロセッサや KaTeX がレンダリング前に置き換えるため、
+    /// 行の結合や途中での分割で働かなくなってしまいます。
+    ///
+    /// # 処理の詳細
+    /// - 指令（`{{#include ...}}`、`{{#rustdoc_include ...}}`、`{{#title ...}}` など）の範囲は
+    ///   テキストから除き、途中で分割しない
+    /// - 指令だけの行は、前後の行と結合しない
+    /// - `$$` で始まる行から、次に `$$` で始まる行までを数式ブロックとし、
+    ///   分割も前後の行との結合もしない（`$$x$$` のような1行の数式ブロックも同様）
+    fn protect_mdbook(&mut self, content: &str, line_starts: &[usize]) {
+        // 現在 `$$` の数式ブロックの内側かどうか
+        let mut in_math = false;
+
+        for (n, &start) in line_starts.iter().enumerate() {
+            let end = line_starts.get(n + 1).map_or(content.len(), |&next| next - 1);
+            let line = &content[start..end];
+            let body = line[self.lines[n].prefix..].trim();
+
+            for directive in REGEX_DIRECTIVE.find_iter(line) {
+                self.lines[n].remove_text(&directive.range());
+                if directive.as_str() == body {
+                    self.protect_line(n);
+                }
+            }
+
+            // コードブロックなどの内側の `$$` は数式ではない
+            if in_math {
+                in_math = !body.starts_with("$$");
+                self.protect_line(n);
+            } else if self.is_prose(n) && body.starts_with("$$") {
+                in_math = body.len() < "$$$$".len() || !body.ends_with("$$");
+                self.protect_line(n);
+            }
+        }
+    }
+
+    /// 行を分割・結合の対象外にする
+    ///
+    /// 行のテキストを取り除き、前の行とも次の行とも結合しないようにします。
+    fn protect_line(&mut self, line: usize) {
+        self.lines[line].text.clear();
+        self.lines[line].soft_break = false;
+        if let Some(previous) = line.checked_sub(1) {
+            self.lines[previous].soft_break = false;
+        }
+    }
+
+    /// 行頭のコンテナの接頭辞のバイト数
+    ///
+    /// 引用の `>`、リストの記号、継続行のインデントからなる部分で、
+    /// 行を結合するときには次の行からこの部分を取り除きます。
+    ///
+    /// # 使用例
+    /// ```rust,ignore
+    /// let document = Document::parse("> - item\n>   text\n");
+    /// assert_eq!(document.prefix(0), 4);
+    /// assert_eq!(document.prefix(1), 4);
+    /// ```
+    pub fn prefix(&self, line: usize) -> usize {
+        self.lines.get(line).map_or(0, |info| info.prefix)
+    }
+
+    /// 行が分割候補の段落テキストを含むかどうか
+    pub fn is_prose(&self, line: usize) -> bool {
+        self.lines.get(line).is_some_and(|info| !info.text.is_empty())
+    }
+
+    /// 行内のバイト位置が分割候補の段落テキストの内側にあるかどうか
+    ///
+    /// インラインコードや HTML タグ、リンク先 URL、
+    /// リンクテキストや強調の内側では `false` です。
+    pub fn is_text(&self, line: usize, offset: usize) -> bool {
+        self.lines
+            .get(line)
+            .is_some_and(|info| info.text.iter().any(|range| range.contains(&offset)))
+    }
+
+    /// 行を次の行と結合できるかどうか
+    ///
+    /// 行末が段落内のソフト改行である場合に `true` です。
+    /// HTML 要素の内側の段落では `false` です。
+    pub fn can_join(&self, line: usize) -> bool {
+        self.lines.get(line).is_some_and(|info| info.soft_break)
+    }
+
+    /// 段落の行範囲の一覧を求める
+    ///
+    /// ここでの段落は、ソフト改行でつながった段落の行のまとまりです。
+    /// 分割・結合はこのまとまりの内側だけで行われるため、
+    /// 整形の前後で段落の数と順序は変わりません。
+    /// 分割でできた強調やインラインコードだけの行も、分割候補のテキストの有無によらず
+    /// 同じまとまりに含めます。
+    ///
+    /// # 戻り値
+    /// 各段落の行範囲（0始まり、終端は含まない）を文書内の順に並べたもの
+    pub fn paragraphs(&self) -> Vec<Range<usize>> {
+        let mut paragraphs = Vec::new();
+        let mut n = 0;
+        while n < self.lines.len() {
+            if !self.lines[n].paragraph && !self.is_prose(n) {
+                n += 1;
+                continue;
+            }
+
+            // ソフト改行で次の行につながる限り延長
+            let start = n;
+            while self.lines[n].soft_break && n + 1 < self.lines.len() {
+                n += 1;
+            }
+            n += 1;
+            paragraphs.push(start..n);
+        }
+        paragraphs
+    }
+}
+
+/// 行頭のコンテナの記号（引用の `>` とリストの記号）と空白
+///
+/// 空文字列にもマッチするため、どの行でも必ずマッチします。
+static REGEX_CONTAINER_PREFIX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?:[ \t]*(?:>|(?:[-*+]|\d{1,9}[.)])(?:[ \t]|$)))*[ \t]*").unwrap()
+});
+
+/// 分割で追加する行の接頭辞を求める
+///
+/// 引用の `>` と空白はそのまま残し、リストの記号は同じ幅の空白に置き換えます。
+/// これにより、追加した行は元の行と同じリスト項目・引用ブロックの継続行になります。
+///
+/// # 使用例
+/// ```rust,ignore
+/// assert_eq!(continuation("> 1. "), ">    ");
+/// assert_eq!(continuation("  - "), "    ");
+/// ```
+pub fn continuation(prefix: &str) -> String {
+    prefix.chars().map(|ch| if ch == '>' || ch.is_whitespace() { ch } else { ' ' }).collect()
+}
+
+/// 内容を持たない HTML の要素（終了タグがない）
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// HTML の開始タグまたは終了タグ（テキストの先頭に固定）
+///
+/// - 1番目のグループ: 終了タグなら `/`
+/// - 2番目のグループ: 要素名
+/// - 3番目のグループ: 自己終了タグ（`<br/>`）なら `/`
+static REGEX_HTML_TAG: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^<(/?)([A-Za-z][A-Za-z0-9-]*)(?:\s(?:[^>"']|"[^"]*"|'[^']*')*?)?(/?)>"#).unwrap()
+});
+
+/// HTML ブロックで開かれた要素の追跡
+///
+/// CommonMark では、`<div class="warning">` の後に空行を置くと、
+/// その内側は Markdown の段落として解析されます。
+/// これらの段落は HTML 要素の一部としてレンダリングされるため、
+/// 要素が閉じられるまでは分割・結合の対象外とします。
+/// 段落内のインライン HTML（`<span>x. y</span>`）の要素の内側も、同じ方法で追跡して分割しません。
+///
+/// # 処理の詳細
+/// - 開始タグで要素名をスタックに積み、対応する終了タグで取り除く
+///   （`<div>` の入れ子も正しく扱う）
+/// - 対応する開始タグのない終了タグは無視し、
+///   閉じられていない内側の要素（`<p>` など）は外側の終了タグでまとめて取り除く
+/// - 空要素（`<br>` など）と自己終了タグ（`<br/>`）は積まない
+/// - コメント（`<!-- ... -->`）の内側は、複数行にわたっても無視する
+#[derive(Debug, Default)]
+struct HtmlElements {
+    /// 開いている要素名（小文字）
+    open: Vec<String>,
+
+    /// コメントの内側にいるかどうか
+    in_comment: bool,
+}
+
+impl HtmlElements {
+    /// HTML ブロックの内容を読み、開いている要素を更新する
+    fn update(&mut self, html: &str) {
+        let mut rest = html;
+        loop {
+            // コメントの終わりまで読み飛ばす
+            if self.in_comment {
+                let Some(end) = rest.find("-->") else {
+                    return;
+                };
+                rest = &rest[end + "-->".len()..];
+                self.in_comment = false;
+            }
+
+            let Some(start) = rest.find('<') else {
+                return;
+            };
+            rest = &rest[start..];
+
+            if let Some(after) = rest.strip_prefix("<!--") {
+                self.in_comment = true;
+                rest = after;
+            } else if let Some(captures) = REGEX_HTML_TAG.captures(rest) {
+                let name = captures[2].to_ascii_lowercase();
+                if !captures[1].is_empty() {
+                    // 終了タグ: 対応する開始タグまでを取り除く
+                    if let Some(index) = self.open.iter().rposition(|open| *open == name) {
+                        self.open.truncate(index);
+                    }
+                } else if captures[3].is_empty() && !VOID_ELEMENTS.contains(&name.as_str()) {
+                    self.open.push(name);
+                }
+                rest = &rest[captures[0].len()..];
+            } else {
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    /// いずれかの要素の内側にいるかどうか
+    fn is_inside(&self) -> bool {
+        !self.open.is_empty()
+    }
+}
+
+/// 行頭に置かれるとブロック構造を開始してしまうテキストかどうか
+///
+/// 文の分割で新しい行の先頭になるテキストがこれに該当すると、
+/// 段落の途中でコードブロックなどが始まり、文書の構造が変わってしまいます。
+/// そのような位置では分割しません。
+///
+/// # 該当するもの
+/// - コードフェンス（3つ以上の `` ` `` または `~`）
+/// - ATX 見出し（`# Title`）
+/// - テーマ区切り（`***`、`- - -` など）
+/// - setext 見出しの下線（`===` または `---`）。直前の行が見出しになってしまう
+/// - 引用ブロック（`> `）
+/// - リスト項目（`- `、`* `、`+ `、`1. `、`1) `）。
+///   `1` 以外で始まる番号付きリストは段落を中断できないため該当しない
+/// - KaTeX の数式ブロック（`$$`）
+/// - 段落を中断できる HTML ブロック（`<!--`、`<div>`、`<pre>` など）。
+///   任意のタグで始まる HTML ブロックは段落を中断できないため該当しない
+///
+/// # 使用例
+/// ```rust,ignore
+/// assert!(starts_block("~~~ opens a fence"));
+/// assert!(starts_block("---"));
+/// assert!(!starts_block("`code` is inline"));
+/// ```
+pub fn starts_block(text: &str) -> bool {
+    let text = text.trim_start();
+    is_code_fence(text)
+        || is_atx_heading(text)
+        || is_thematic_break(text)
+        || is_setext_underline(text)
+        || REGEX_CONTAINER_START.is_match(text)
+        || text.starts_with("$$")
+        || REGEX_HTML_BLOCK_START.is_match(text)
+}
+
+/// テーブルの区切り行（`|---|:---:|` など）かどうか
+///
+/// GFM のテーブルは、ヘッダー行と区切り行の列数が一致すれば段落を中断して始まります。
+/// 区切り行の直前の行を分割すると、最後の部分がヘッダー行になることがあります。
+///
+/// # 使用例
+/// ```rust,ignore
+/// assert!(is_table_delimiter("|---|:---:|"));
+/// assert!(!is_table_delimiter("---"));
+/// ```
+pub fn is_table_delimiter(text: &str) -> bool {
+    REGEX_TABLE_DELIMITER.is_match(text.trim())
+}
+
+/// テーブルの区切り行
+static REGEX_TABLE_DELIMITER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?:\|?[ \t]*:?-+:?[ \t]*\|)+(?:[ \t]*:?-+:?[ \t]*)?$|^\|[ \t]*:?-+:?[ \t]*$")
+        .unwrap()
+});
+
+/// 行だけで完結するリンク参照定義（`[label]: destination` など）になるテキストかどうか
+///
+/// 段落の最初の行の後ろで分割すると、残った最初の行がリンク参照定義として解釈され、
+/// 本文から消えてしまうことがあります（`[`name`]: 説明文。` のような項目）。
+/// 宛先は次の行に置けるため、`[label]:` だけの行も該当します。
+/// 行頭のコンテナの接頭辞（`- ` や `> `）は取り除いて判定します。
+///
+/// # 使用例
+/// ```rust,ignore
+/// assert!(is_link_definition("- [`name`]: 説明文です。"));
+/// assert!(!is_link_definition("[`name`]: the description."));
+/// ```
+pub fn is_link_definition(text: &str) -> bool {
+    let prefix = REGEX_CONTAINER_PREFIX.find(text).map_or(0, |prefix| prefix.end());
+    REGEX_LINK_DEFINITION.is_match(&text[prefix..])
+}
+
+/// 行だけで完結するリンク参照定義
+static REGEX_LINK_DEFINITION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"^\[(?:[^\]\\]|\\.)+\]:[ \t]*(?:(?:<[^>]*>|\S+)(?:[ \t]+(?:"[^"]*"|'[^']*'|\([^)]*\)))?)?[ \t]*$"#,
+    )
+    .unwrap()
+});
+
+/// mdBook のプリプロセッサの指令（`{{#include file.rs}}` など）
+static REGEX_DIRECTIVE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{\{\s*#[\w-]+[^}]*\}\}").unwrap());
+
+/// 段落を中断して始まる引用ブロックまたはリスト項目
+static REGEX_CONTAINER_START: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(?:>|(?:[-*+]|1[.)])(?:[ \t]|$))").unwrap());
+
+/// 段落を中断して始まる HTML ブロック（CommonMark の HTML ブロックの種類 1〜6）
+static REGEX_HTML_BLOCK_START: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)^<(?:!--|\?|![a-z]|!\[CDATA\[|(?:script|pre|style|textarea)(?:[\s>]|$)|/?(?:address|article|aside|base|basefont|blockquote|body|caption|center|col|colgroup|dd|details|dialog|dir|div|dl|dt|fieldset|figcaption|figure|footer|form|frame|frameset|h[1-6]|head|header|hr|html|iframe|legend|li|link|main|menu|menuitem|nav|noframes|ol|optgroup|option|p|param|search|section|summary|table|tbody|td|tfoot|th|thead|title|tr|track|ul)(?:[\s>]|/>|$))",
+    )
+    .unwrap()
+});
+
+/// コードフェンスの開始行かどうか
+///
+/// CommonMark では、3つ以上の `` ` `` または `~` でフェンスが始まります。
+/// バッククォートのフェンスの情報文字列にはバッククォートを含められないため、
+/// ```` ```a``` ```` のような行はインラインコードでありフェンスではありません。
+fn is_code_fence(text: &str) -> bool {
+    let Some(fence) = text.chars().next().filter(|&ch| matches!(ch, '`' | '~')) else {
+        return false;
+    };
+    let length = text.len() - text.trim_start_matches(fence).len();
+    length >= 3 && (fence == '~' || !text[length..].contains('`'))
+}
+
+/// ATX 見出しの開始行かどうか
+///
+/// 1〜6個の `#` の後に空白が続くか、行が終わる場合に見出しになります。
+/// `#1` のように空白が続かなければ見出しではありません。
+fn is_atx_heading(text: &str) -> bool {
+    let rest = text.trim_start_matches('#');
+    (1..=6).contains(&(text.len() - rest.len()))
+        && (rest.is_empty() || rest.starts_with([' ', '\t']))
+}
+
+/// テーマ区切りの行かどうか
+///
+/// 同じ記号（`*`、`-`、`_`）が3つ以上並び、その間と前後には空白しかない行です。
+fn is_thematic_break(text: &str) -> bool {
+    let Some(marker) = text.chars().next().filter(|&ch| matches!(ch, '*' | '-' | '_')) else {
+        return false;
+    };
+    text.chars().all(|ch| ch == marker || ch == ' ' || ch == '\t')
+        && text.chars().filter(|&ch| ch == marker).count() >= 3
+}
+
+/// setext 見出しの下線の行かどうか
+///
+/// 1つ以上の `=` または `-` だけが並び、後ろに空白しかない行です。
+/// 段落の直後に置かれると、その段落が見出しになります。
+fn is_setext_underline(text: &str) -> bool {
+    let Some(marker) = text.chars().next().filter(|&ch| matches!(ch, '=' | '-')) else {
+        return false;
+    };
+    text.trim_start_matches(marker).trim_end().is_empty()
+}
+
+/// ブロック要素の開始タグかどうか
+///
+/// 強調やリンクなどのインライン要素は、文脈の判定に影響しないため除外します。
+/// これらは分割しないインライン要素として別に数えます。
+pub(crate) fn is_block(tag: &Tag<'_>) -> bool {
+    !matches!(
+        tag,
+        Tag::Emphasis
+            | Tag::Strong
+            | Tag::Strikethrough
+            | Tag::Superscript
+            | Tag::Subscript
+            | Tag::Link { .. }
+            | Tag::Image { .. }
+    )
+}
+
+/// ブロック要素の終了タグかどうか
+pub(crate) fn is_block_end(tag: TagEnd) -> bool {
+    !matches!(
+        tag,
+        TagEnd::Emphasis
+            | TagEnd::Strong
+            | TagEnd::Strikethrough
+            | TagEnd::Superscript
+            | TagEnd::Subscript
+            | TagEnd::Link
+            | TagEnd::Image
+    )
+}
+
+/// 現在の文脈が分割・結合の対象となる文章かどうか
+///
+/// 最も内側のブロックが段落、または tight なリスト項目
+/// （段落タグを伴わずにテキストが直接置かれる）の場合に `true` です。
+/// 引用ブロックやリスト項目の内側の段落も対象です。
+fn is_prose(blocks: &[Tag<'_>]) -> bool {
+    matches!(blocks.last(), Some(Tag::Paragraph | Tag::Item))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Document, HtmlElements, continuation, is_link_definition, is_table_delimiter, starts_block,
+    };
+    use crate::warning::WarningKind;
+
+    /// 構造的に保護される行の判定テスト
+    #[test]
+    fn test_document() {
+        let content = "\
+# heading. here
+prose with `code. span` here
+
+| a. b | c |
+|------|---|
+| d. e | f |
+
+[target]: https://example.com \"a. b\"
+```
+code. block
+```
+> quote. here
+- item. here
+";
+        let document = Document::parse(content);
+
+        // 見出し、テーブル、リンク参照定義、コードブロックは対象外
+        for line in [0, 2, 3, 4, 5, 6, 7, 8, 9, 10] {
+            assert!(!document.is_prose(line), "line {line}");
+        }
+
+        // 段落、引用、リスト項目は対象
+        assert!(document.is_prose(1));
+        assert!(document.is_prose(11));
+        assert!(document.is_prose(12));
+
+        // インラインコードの内側はテキストではない
+        assert!(document.is_text(1, 0));
+        assert!(!document.is_text(1, 17));
+    }
+
+    /// GFM のテーブルの判定テスト
+    ///
+    /// テーブルはヘッダー行と区切り行で始まり、空行または他のブロックの開始で終わります。
+    /// `|` を含むだけの段落の行はテーブルではありません。
+    #[test]
+    fn test_document_tables() {
+        let content = "\
+Use `|x| x + 1`. Pipes a | b. And || too.
+ls | grep x. ok
+
+| a. b | c |
+|------|---|
+| d. e | f |
+lazy. row
+# heading
+
+not | a table. no delimiter
+| still. prose |
+";
+        let document = Document::parse(content);
+
+        // インラインコードの外側の `|` は段落のテキスト
+        assert!(document.is_prose(0));
+        assert!(!document.is_text(0, 6));
+        assert!(document.is_text(0, 20));
+        assert!(document.can_join(0));
+        assert!(document.is_prose(1));
+
+        // ヘッダー行から、区切り行のない行（GFM では行として扱われる）までがテーブル
+        for line in 3..=7 {
+            assert!(!document.is_prose(line), "line {line}");
+        }
+
+        // 区切り行がなければテーブルではない
+        assert!(document.is_prose(9));
+        assert!(document.is_prose(10));
+    }
+
+    /// mdBook 固有の構文の判定テスト
+    #[test]
+    fn test_document_mdbook() {
+        let content = "\
+{{#title Title. Here}}
+Run this:
+{{#include ../../examples/a.rs}}
+See {{#rustdoc_include a.rs:1:2}} here. ok
+$$
+a. b
+$$
+after. math
+$$x. y$$
+```mermaid
+graph TD. A-->B
+```
+";
+        let document = Document::parse(content);
+
+        // 指令だけの行は対象外で、前後の行とも結合しない
+        assert!(!document.is_prose(0));
+        assert!(!document.can_join(1));
+        assert!(!document.is_prose(2));
+        assert!(!document.can_join(2));
+
+        // 行の途中の指令は、その範囲だけがテキストでない
+        assert!(document.is_prose(3));
+        assert!(document.is_text(3, 2));
+        assert!(!document.is_text(3, 10));
+        assert!(document.is_text(3, 36));
+
+        // 数式ブロックは対象外で、前後の行とも結合しない
+        assert!(!document.can_join(3));
+        for line in [4, 5, 6, 8, 10] {
+            assert!(!document.is_prose(line), "line {line}");
+        }
+        assert!(document.is_prose(7));
+        assert!(!document.can_join(7));
+    }
+
+    /// 整形を抑制するコメントの判定テスト
+    #[test]
+    fn test_document_suppression() {
+        let content = "\
+before. text
+<!-- sembr-ignore -->
+next. block
+still. next
+
+after. block
+<!-- sembr-ignore-start -->
+region. one
+
+region. two
+<!-- sembr-ignore-end -->
+outside. region
+<!-- sembr-ignore-end -->
+<!-- sembr-ignore-start -->
+unterminated. region
+";
+        let document = Document::parse(content);
+
+        assert!(document.is_prose(0));
+        assert!(!document.can_join(0));
+        for line in [2, 3, 7, 9, 14] {
+            assert!(!document.is_prose(line), "line {line}");
+        }
+        assert!(!document.can_join(2));
+        assert!(document.is_prose(5));
+        assert!(document.is_prose(11));
+
+        let warnings: Vec<_> =
+            document.warnings().iter().map(|warning| (warning.line, warning.kind)).collect();
+        assert_eq!(
+            [(13, WarningKind::UnmatchedIgnoreEnd), (14, WarningKind::UnterminatedIgnore)],
+            warnings.as_slice(),
+        );
+
+        let document = Document::parse("<!-- sembr-ignore-file -->\n\nprose. here\n");
+        assert!(!document.is_prose(2));
+        assert!(document.warnings().is_empty());
+    }
+
+    /// コンテナの接頭辞の判定テスト
+    #[test]
+    fn test_document_prefix() {
+        let content = "\
+> - item
+>   text `code
+> span` more
+> > nested
+lazy
+1. first
+   text
+   2019. was a year
+   - [ ] task
+";
+        let document = Document::parse(content);
+
+        assert_eq!([4, 4, 2, 4, 0, 3, 3, 3, 5], std::array::from_fn(|line| document.prefix(line)),);
+
+        assert_eq!(">   ", continuation("> - "));
+        assert_eq!(">    ", continuation("> 1. "));
+        assert_eq!("      ", continuation("  10) "));
+        assert_eq!("> > ", continuation("> > "));
+    }
+
+    /// フェンスの文字と長さ、インデントによるコードブロックの判定テスト
+    #[test]
+    fn test_document_code_blocks() {
+        let content = "\
+````markdown
+```rust
+not. prose
+```
+````
+~~~
+tilde. fence
+```
+still. code
+~~~
+
+    indented. code
+
+prose. here
+";
+        let document = Document::parse(content);
+
+        for line in 0..=12 {
+            assert!(!document.is_prose(line), "line {line}");
+        }
+        assert!(document.is_prose(13));
+    }
+
+    /// HTML 要素の内側の段落の判定テスト
+    #[test]
+    fn test_document_html() {
+        let content = "\
+<div class=\"warning\">
+
+inside. div
+text
+
+</div>
+
+<details>
+<summary>Title</summary>
+<div>
+
+nested. inside
+</div>
+
+still. inside
+</details>
+
+<!--
+date-check. comment
+<div>
+-->
+
+outside. prose
+<br>
+
+after. br
+";
+        let document = Document::parse(content);
+
+        for line in [2, 3, 11, 14, 18] {
+            assert!(!document.is_prose(line), "line {line}");
+        }
+        assert!(!document.can_join(2));
+        assert!(document.is_prose(22));
+        assert!(document.is_prose(25));
+    }
+
+    /// HTML 要素の追跡のテスト
+    #[test]
+    fn test_html_elements() {
+        let mut html = HtmlElements::default();
+
+        html.update("<div class=\"a > b\"><p>text");
+        assert_eq!(["div", "p"], html.open.as_slice());
+
+        // 閉じられていない `<p>` は外側の終了タグで取り除く
+        html.update("</DIV>");
+        assert!(!html.is_inside());
+
+        // 空要素、自己終了タグ、対応のない終了タグ、コメントは無視する
+        html.update("<img src=\"a.png\"><br/></span><!-- <div> -->");
+        assert!(!html.is_inside());
+
+        html.update("<!--");
+        html.update("<div>");
+        html.update("-->");
+        assert!(!html.is_inside());
+    }
+
+    /// ブロック構造を開始するテキストの判定テスト
+    #[test]
+    fn test_starts_block() {
+        assert!(starts_block("```"));
+        assert!(starts_block("```rust"));
+        assert!(starts_block("  ~~~ text"));
+        assert!(starts_block("~~~ `tilde` info"));
+        assert!(starts_block("````"));
+
+        assert!(!starts_block("``"));
+        assert!(!starts_block("```a```"));
+        assert!(!starts_block("text ```"));
+
+        assert!(starts_block("# Title"));
+        assert!(starts_block("###### Title"));
+        assert!(starts_block("#"));
+        assert!(!starts_block("####### Title"));
+        assert!(!starts_block("#1 issue"));
+
+        assert!(starts_block("***"));
+        assert!(starts_block("- - -"));
+        assert!(starts_block("___  "));
+        assert!(!starts_block("** bold"));
+        assert!(!starts_block("-- dash"));
+
+        assert!(starts_block("==="));
+        assert!(starts_block("="));
+        assert!(starts_block("--  "));
+        assert!(!starts_block("= sign"));
+        assert!(!starts_block("==> arrow"));
+
+        assert!(starts_block("> quote"));
+        assert!(starts_block("- item"));
+        assert!(starts_block("+ item"));
+        assert!(starts_block("1. item"));
+        assert!(starts_block("1) item"));
+        assert!(starts_block("*"));
+        assert!(starts_block("1."));
+        assert!(!starts_block("2019. was a year"));
+        assert!(!starts_block("-> arrow"));
+
+        assert!(starts_block("$$"));
+        assert!(!starts_block("$5 each"));
+
+        // 段落を中断できる HTML ブロック
+        assert!(starts_block("<!-- date-check --> 2024年1月現在"));
+        assert!(starts_block("<div class=\"warning\">"));
+        assert!(starts_block("</details>"));
+        assert!(starts_block("<PRE>"));
+        assert!(!starts_block("<span>inline</span>"));
+        assert!(!starts_block("<https://example.com>"));
+        assert!(!starts_block("<divider>"));
+    }
+
+    /// テーブルの区切り行の判定のテスト
+    #[test]
+    fn test_is_table_delimiter() {
+        assert!(is_table_delimiter("|---|---|"));
+        assert!(is_table_delimiter(" :--- | ---: "));
+        assert!(is_table_delimiter("|:-:|"));
+        assert!(!is_table_delimiter("---"));
+        assert!(!is_table_delimiter("a | b"));
+        assert!(!is_table_delimiter("-> arrow |"));
+    }
+
+    /// リンク参照定義になる行の判定のテスト
+    #[test]
+    fn test_is_link_definition() {
+        assert!(is_link_definition("[`name`]: 説明文です。"));
+        assert!(is_link_definition("- [`name`]: 説明文です。 "));
+        assert!(is_link_definition("> [a]: <b c> \"title\""));
+        assert!(is_link_definition("- [`name`]:"));
+        assert!(!is_link_definition("[`name`]: the description."));
+        assert!(!is_link_definition("see [`name`]: 説明文です。"));
+    }
+
+    /// 結合候補となる行末の判定テスト
+    #[test]
+    fn test_document_soft_breaks() {
+        let content = "\
+soft
+break
+hard\\
+break
+<div>
+
+in div
+text
+</div>
+";
+        let document = Document::parse(content);
+
+        assert!(document.can_join(0));
+        assert!(document.can_join(1));
+        assert!(!document.can_join(2));
+        assert!(!document.can_join(3));
+        assert!(!document.can_join(6));
+    }
+
+    /// 段落の行範囲の判定テスト
+    #[test]
+    fn test_document_paragraphs() {
+        let content = "\
+# heading
+
+first
+paragraph\\
+after hard break
+
+- item
+  continued
+- next item
+
+```
+code
+```
+last
+*emphasis only*
+`code`
+";
+        let document = Document::parse(content);
+
+        // 強調やインラインコードだけの行も、同じ段落に含める
+        assert_eq!(vec![2..4, 4..5, 6..8, 8..9, 13..16], document.paragraphs());
+    }
+}
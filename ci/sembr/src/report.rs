@@ -0,0 +1,292 @@
+//! チェック結果の機械可読な出力（JSON と SARIF）
+//!
+//! チェックモードで非適合のファイル名だけを表示しても、
+//! レビュアーやボットにはどの行が問題なのか分かりません。
+//! このモジュールは、ライブラリが求めた行単位の変更を違反として、
+//! JSON または SARIF（コードスキャンの標準形式）で出力します。
+//!
+//! 整形では解消できない問題（[`Warning`]）は、どの形式でも標準エラー出力に表示し、
+//! SARIF では修正案のない結果としても出力します。
+
+use std::fmt;
+use std::path::Path;
+
+use clap::ValueEnum;
+use sembr::{Edit, Rule, Warning, WarningKind};
+use serde::Serialize;
+use serde_json::json;
+
+/// チェック結果の出力形式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// 人間向けのファイル一覧（`not compliant:` など）
+    #[default]
+    Text,
+    /// 違反ごとのオブジェクトの配列
+    Json,
+    /// SARIF 2.1.0（GitHub のコードスキャンなどが読み込める形式）
+    Sarif,
+}
+
+/// 1つの違反
+///
+/// ライブラリが返す変更（[`Edit`]）に、ファイルのパスを加えたものです。
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Violation {
+    /// ファイルのパス
+    pub path: String,
+    /// 置き換える行範囲、規則、置き換え後のテキスト
+    #[serde(flatten)]
+    pub edit: Edit,
+}
+
+impl Violation {
+    /// ファイルの変更の一覧を違反の一覧に変換する
+    pub fn from_edits(path: &Path, edits: Vec<Edit>) -> impl Iterator<Item = Self> {
+        let path = path.display().to_string();
+        edits.into_iter().map(move |edit| Violation { path: path.clone(), edit })
+    }
+}
+
+/// 1つのファイルの警告
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileWarning {
+    /// ファイルのパス
+    pub path: String,
+    /// 警告の行と種類
+    pub warning: Warning,
+}
+
+impl FileWarning {
+    /// ファイルの警告の一覧を、パス付きの警告の一覧に変換する
+    pub fn from_warnings(path: &Path, warnings: Vec<Warning>) -> impl Iterator<Item = Self> {
+        let path = path.display().to_string();
+        warnings.into_iter().map(move |warning| FileWarning { path: path.clone(), warning })
+    }
+}
+
+impl fmt::Display for FileWarning {
+    /// `warning: path:line: description` の形式で表示する
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Warning { line, kind } = &self.warning;
+        write!(f, "warning: {}:{line}: {}", self.path, kind.description())
+    }
+}
+
+/// 違反の一覧を JSON で出力する
+///
+/// # 出力例
+/// ```json
+/// [
+///   {
+///     "path": "src/a.md",
+///     "start_line": 3,
+///     "end_line": 3,
+///     "rule": "needs-split",
+///     "replacement": "foo.\nbar\n"
+///   }
+/// ]
+/// ```
+pub fn to_json(violations: &[Violation]) -> String {
+    serde_json::to_string_pretty(violations).unwrap()
+}
+
+/// 違反と警告の一覧を SARIF 2.1.0 で出力する
+///
+/// 各違反は置き換え内容を `fixes` に持つため、
+/// 対応するツールでは修正案として表示されます。
+/// 修正案の領域は行全体（最後の行の改行を除く）なので、置き換え後のテキストも最後の改行を除きます。
+/// 警告は修正案を持たない結果として出力します。
+pub fn to_sarif(violations: &[Violation], warnings: &[FileWarning]) -> String {
+    let rules: Vec<_> = [Rule::NeedsSplit, Rule::CanJoin]
+        .into_iter()
+        .map(|rule| (rule.id(), rule.description()))
+        .chain(
+            [
+                WarningKind::UnterminatedIgnore,
+                WarningKind::UnmatchedIgnoreEnd,
+                WarningKind::LineTooLong,
+                WarningKind::MixedLineEndings,
+            ]
+            .into_iter()
+            .map(|kind| (kind.id(), kind.description())),
+        )
+        .map(|(id, description)| {
+            json!({
+                "id": id,
+                "shortDescription": { "text": description },
+            })
+        })
+        .collect();
+
+    let warnings = warnings.iter().map(|FileWarning { path, warning }| {
+        json!({
+            "ruleId": warning.kind.id(),
+            "level": "warning",
+            "message": { "text": warning.kind.description() },
+            "locations": [{
+                "physicalLocation": {
+                    "artifactLocation": { "uri": uri(path) },
+                    "region": { "startLine": warning.line },
+                },
+            }],
+        })
+    });
+
+    let results: Vec<_> = violations
+        .iter()
+        .map(|violation| {
+            let location = json!({ "uri": uri(&violation.path) });
+            let edit = &violation.edit;
+            let region = json!({
+                "startLine": edit.start_line,
+                "endLine": edit.end_line,
+            });
+            // 列を省略した領域は最後の行の改行を含まないため、置き換え後のテキストからも除く
+            // （ファイルの末尾に改行がない場合も、同じ領域で置き換えられる）
+            let replacement = edit.replacement.strip_suffix('\n').unwrap_or(&edit.replacement);
+            let replacement = replacement.strip_suffix('\r').unwrap_or(replacement);
+            json!({
+                "ruleId": edit.rule.id(),
+                "level": "warning",
+                "message": { "text": edit.rule.description() },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": location,
+                        "region": region,
+                    },
+                }],
+                "fixes": [{
+                    "description": { "text": "Apply sembr formatting." },
+                    "artifactChanges": [{
+                        "artifactLocation": location,
+                        "replacements": [{
+                            "deletedRegion": region,
+                            "insertedContent": { "text": replacement },
+                        }],
+                    }],
+                }],
+            })
+        })
+        .chain(warnings)
+        .collect();
+
+    let sarif = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "sembr",
+                    "rules": rules,
+                },
+            },
+            "results": results,
+        }],
+    });
+    serde_json::to_string_pretty(&sarif).unwrap()
+}
+
+/// パスを SARIF の相対 URI に変換する
+///
+/// 区切り文字を `/` に揃え、先頭の `./` を取り除きます。
+fn uri(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    path.strip_prefix("./").map(str::to_owned).unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use sembr::{Edit, Formatter, Options, Rule, Warning, WarningKind};
+
+    use super::{FileWarning, Violation, to_json, to_sarif};
+
+    /// JSON と SARIF の出力のテスト
+    #[test]
+    fn test_report() {
+        let edits = vec![
+            Edit {
+                start_line: 3,
+                end_line: 3,
+                rule: Rule::NeedsSplit,
+                replacement: "first.\nsecond\n".to_owned(),
+            },
+            Edit {
+                start_line: 6,
+                end_line: 7,
+                rule: Rule::CanJoin,
+                replacement: "short line\n".to_owned(),
+            },
+        ];
+        let violations: Vec<_> = Violation::from_edits(Path::new("./a.md"), edits).collect();
+        let warning = Warning { line: 9, kind: WarningKind::UnterminatedIgnore };
+        let warnings: Vec<_> =
+            FileWarning::from_warnings(Path::new("./a.md"), vec![warning]).collect();
+        assert!(warnings[0].to_string().starts_with("warning: ./a.md:9: `sembr-ignore-start`"));
+
+        let json: serde_json::Value = serde_json::from_str(&to_json(&violations)).unwrap();
+        assert_eq!("./a.md", json[0]["path"]);
+        assert_eq!(3, json[0]["start_line"]);
+        assert_eq!("needs-split", json[0]["rule"]);
+        assert_eq!("first.\nsecond\n", json[0]["replacement"]);
+
+        let sarif: serde_json::Value =
+            serde_json::from_str(&to_sarif(&violations, &warnings)).unwrap();
+        let result = &sarif["runs"][0]["results"][1];
+        assert_eq!("can-join", result["ruleId"]);
+        assert_eq!(6, result["locations"][0]["physicalLocation"]["region"]["startLine"]);
+        assert_eq!("a.md", result["locations"][0]["physicalLocation"]["artifactLocation"]["uri"]);
+
+        let result = &sarif["runs"][0]["results"][2];
+        assert_eq!("unterminated-ignore", result["ruleId"]);
+        assert_eq!(9, result["locations"][0]["physicalLocation"]["region"]["startLine"]);
+        assert!(result["fixes"].is_null());
+    }
+
+    /// SARIF の修正案の置き換えを適用する
+    ///
+    /// 列を省略した領域は、開始行の先頭から最後の行の改行の直前までです。
+    fn apply_sarif_fix(content: &str, replacement: &serde_json::Value) -> String {
+        let region = &replacement["deletedRegion"];
+        let start_line = region["startLine"].as_u64().unwrap() as usize;
+        let end_line = region["endLine"].as_u64().unwrap() as usize;
+
+        let line_starts: Vec<usize> = std::iter::once(0)
+            .chain(content.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+        let start = line_starts[start_line - 1];
+        let line_end = line_starts.get(end_line).map_or(content.len(), |&next| next - 1);
+        let end = if content[..line_end].ends_with('\r') { line_end - 1 } else { line_end };
+
+        let inserted = replacement["insertedContent"]["text"].as_str().unwrap();
+        format!("{}{inserted}{}", &content[..start], &content[end..])
+    }
+
+    /// SARIF の修正案を適用すると、整形結果と一致することのテスト
+    #[test]
+    fn test_sarif_fixes() {
+        let formatter = Formatter::new(Options::default()).unwrap();
+        for content in [
+            "# title\n\nFirst. Second.\n\nshort\nline\n",
+            "# title\r\n\r\nFirst. Second.\r\n",
+            "short\nline",
+            "First. Second.",
+        ] {
+            let violations: Vec<_> =
+                Violation::from_edits(Path::new("a.md"), formatter.check_str(content)).collect();
+            assert!(!violations.is_empty());
+            let sarif: serde_json::Value =
+                serde_json::from_str(&to_sarif(&violations, &[])).unwrap();
+
+            // 後ろの修正から適用し、前の修正の行番号をずらさない
+            let mut fixed = content.to_owned();
+            for result in sarif["runs"][0]["results"].as_array().unwrap().iter().rev() {
+                let replacement = &result["fixes"][0]["artifactChanges"][0]["replacements"][0];
+                fixed = apply_sarif_fix(&fixed, replacement);
+            }
+            assert_eq!(formatter.format_str(content), fixed, "{content:?}");
+        }
+    }
+}
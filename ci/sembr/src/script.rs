@@ -0,0 +1,233 @@
+//! 文字種（スクリプト）に応じた行の結合
+//!
+//! 日本語などの CJK 文字は単語の間に空白を置かないため、
+//! 2つの行を結合するときに常に空白を挿入すると、
+//! レンダリング結果に余計な空白が混入してしまいます。
+//! このモジュールは、結合位置の前後の文字種を見て
+//! 空白を挿入するかどうかを決定します。
+
+use serde::Deserialize;
+
+/// CJK文字とラテン文字の境界で行を結合する際の空白の扱い
+///
+/// `Rustの` と `compiler` のように、片側が CJK 文字、
+/// もう片側がラテン文字などの場合に適用されます。
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum CjkLatinSpacing {
+    /// 空白を挿入しない（`Rustのcompiler`）
+    #[default]
+    Omit,
+    /// 半角空白を1つ挿入する（`Rustの compiler`）
+    Space,
+}
+
+/// 結合規則の判定に使う文字種
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Script {
+    /// 漢字・ひらがな・カタカナ
+    Cjk,
+    /// 全角の句読点・括弧・記号
+    ///
+    /// これらは文字自体に空白を含むため、
+    /// 前後の文字種に関わらず空白を挿入しません。
+    CjkPunctuation,
+    /// ラテン文字・数字・半角記号など、その他すべて
+    Other,
+}
+
+/// 文字の文字種を判定する
+///
+/// ハングルは単語を空白で区切るため、CJK 文字としては扱いません。
+fn script(ch: char) -> Script {
+    match ch {
+        // CJK記号と句読点（、。「」など）
+        '\u{3000}'..='\u{303F}'
+        // 全角英数字と全角記号（！（）？など）
+        | '\u{FF00}'..='\u{FF60}'
+        // 半角の句読点と括弧（｡｢｣､）
+        | '\u{FF61}'..='\u{FF64}' => Script::CjkPunctuation,
+        // ひらがな・カタカナ
+        '\u{3040}'..='\u{30FF}'
+        // カタカナ拡張
+        | '\u{31F0}'..='\u{31FF}'
+        // CJK統合漢字拡張A
+        | '\u{3400}'..='\u{4DBF}'
+        // CJK統合漢字
+        | '\u{4E00}'..='\u{9FFF}'
+        // CJK互換漢字
+        | '\u{F900}'..='\u{FAFF}'
+        // 半角カタカナ
+        | '\u{FF65}'..='\u{FF9F}'
+        // CJK統合漢字拡張B以降
+        | '\u{20000}'..='\u{3FFFF}' => Script::Cjk,
+        _ => Script::Other,
+    }
+}
+
+/// 強調の区切り文字（`*`、`_`、`~`）かどうか
+fn is_delimiter(ch: char) -> bool {
+    matches!(ch, '*' | '_' | '~')
+}
+
+/// CommonMark の flanking の規則で、区切り文字の並びが強調を開始・終了できるかを判定する
+///
+/// 行頭・行末は空白として扱います（`None`）。
+/// Unicode の句読点・記号は、英数字と空白以外の文字で近似します。
+///
+/// # 引数
+/// - `delimiter`: 区切り文字
+/// - `previous`: 区切り文字の並びの直前の文字
+/// - `next`: 区切り文字の並びの直後の文字
+///
+/// # 戻り値
+/// 強調を開始できるか、終了できるかの組
+fn flanking(delimiter: char, previous: Option<char>, next: Option<char>) -> (bool, bool) {
+    let is_whitespace = |ch: Option<char>| ch.is_none_or(char::is_whitespace);
+    let is_punctuation =
+        |ch: Option<char>| ch.is_some_and(|ch| !ch.is_alphanumeric() && !ch.is_whitespace());
+
+    let left = !is_whitespace(next)
+        && (!is_punctuation(next) || is_whitespace(previous) || is_punctuation(previous));
+    let right = !is_whitespace(previous)
+        && (!is_punctuation(previous) || is_whitespace(next) || is_punctuation(next));
+    if delimiter == '_' {
+        // `_` は単語の内側では強調にならない
+        (left && (!right || is_punctuation(previous)), right && (!left || is_punctuation(next)))
+    } else {
+        (left, right)
+    }
+}
+
+/// 2つの行の間に置く区切り文字列を決定する
+///
+/// # 規則
+/// - 結合で強調の区切り文字（`*`、`_`、`~`）の働きが変わる場合: 半角空白1つ
+///   （行末の区切り文字は次の行の先頭の文字と、行頭の区切り文字は前の行の末尾の文字と隣り合うため。
+///   行末では強調を終了できなくなる場合、行頭では開始できなくなる場合と、
+///   どちらもできなかった区切り文字が新たに強調を開始・終了できるようになる場合。[`flanking`] を参照）
+/// - 片側が全角の句読点・括弧: 空白なし
+/// - 両側が CJK 文字: 空白なし
+/// - 両側がその他の文字: 半角空白1つ
+/// - CJK 文字とその他の文字の境界: `spacing` に従う
+///
+/// # 引数
+/// - `line`: 前の行（空でない）
+/// - `next_line`: 次の行（先頭の空白を除いたもの、空でない）
+/// - `spacing`: CJK/ラテン境界での空白の扱い
+fn separator(line: &str, next_line: &str, spacing: CjkLatinSpacing) -> &'static str {
+    let before = line.chars().next_back().unwrap();
+    let after = next_line.chars().next().unwrap();
+
+    // 同じ区切り文字が続くと、1つの並びになってしまう
+    if is_delimiter(before) && before == after {
+        return " ";
+    }
+    // 行末の区切り文字の並び（改行の前では終了だけができる）
+    if is_delimiter(before) {
+        let previous = line.trim_end_matches(before).chars().next_back();
+        let (_, closes) = flanking(before, previous, None);
+        let (open, close) = flanking(before, previous, Some(after));
+        if close != closes || (open && !closes) {
+            return " ";
+        }
+    }
+    // 行頭の区切り文字の並び（改行の後では開始だけができる）
+    if is_delimiter(after) {
+        let next = next_line.trim_start_matches(after).chars().next();
+        let (opens, _) = flanking(after, None, next);
+        let (open, close) = flanking(after, Some(before), next);
+        if open != opens || (close && !opens) {
+            return " ";
+        }
+    }
+
+    match (script(before), script(after)) {
+        (Script::CjkPunctuation, _) | (_, Script::CjkPunctuation) => "",
+        (Script::Cjk, Script::Cjk) => "",
+        (Script::Other, Script::Other) => " ",
+        _ => match spacing {
+            CjkLatinSpacing::Omit => "",
+            CjkLatinSpacing::Space => " ",
+        },
+    }
+}
+
+/// 文字に隣接する空白を、行の結合で省略しうるかどうか
+///
+/// CJK 文字や全角の句読点に隣接する空白は、設定によっては結合時に挿入されません。
+/// 整形前後のレンダリング結果の比較では、このような空白の有無を区別しません。
+pub(crate) fn omits_space(ch: char) -> bool {
+    script(ch) != Script::Other
+}
+
+/// 2つの行を文字種に応じて結合する
+///
+/// 次の行の先頭の空白を取り除いたうえで、
+/// [`separator`] が決定した区切り文字列で連結します。
+///
+/// # 引数
+/// - `line`: 前の行
+/// - `next_line`: 次の行
+/// - `spacing`: CJK/ラテン境界での空白の扱い
+///
+/// # 戻り値
+/// 結合後の行
+///
+/// # 使用例
+/// ```rust,ignore
+/// assert_eq!(join_lines("分割しない", "短い文", CjkLatinSpacing::Omit), "分割しない短い文");
+/// assert_eq!(join_lines("do not", "split", CjkLatinSpacing::Omit), "do not split");
+/// ```
+pub fn join_lines(line: &str, next_line: &str, spacing: CjkLatinSpacing) -> String {
+    let next_line = next_line.trim_start();
+
+    // どちらかが空なら、区切り文字は不要
+    if line.is_empty() || next_line.is_empty() {
+        return format!("{line}{next_line}");
+    }
+
+    format!("{line}{}{next_line}", separator(line, next_line, spacing))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CjkLatinSpacing, join_lines};
+
+    /// 文字種の組み合わせごとの結合規則のテスト
+    #[test]
+    fn test_join_lines() {
+        let omit = CjkLatinSpacing::Omit;
+        let space = CjkLatinSpacing::Space;
+
+        // CJK同士は空白なし
+        assert_eq!("分割しない短い文", join_lines("分割しない", "短い文", omit));
+        assert_eq!("カタカナとひらがな", join_lines("カタカナ", "とひらがな", space));
+
+        // ラテン文字同士は空白1つ
+        assert_eq!("do not split", join_lines("do not", "  split", omit));
+
+        // CJK/ラテン境界は設定に従う
+        assert_eq!("Rustのcompiler", join_lines("Rustの", "compiler", omit));
+        assert_eq!("Rustの compiler", join_lines("Rustの", "compiler", space));
+        assert_eq!("`rustc`を使う", join_lines("`rustc`", "を使う", omit));
+
+        // 強調の区切り文字が開始・終了できなくなる場合は、CJK 文字の隣でも空白を入れる
+        assert_eq!("**場合、** それを", join_lines("**場合、**", "それを", omit));
+        assert_eq!("すべての _歴史的_", join_lines("すべての", "_歴史的_", omit));
+        assert_eq!("*a* *b*", join_lines("*a*", "*b*", omit));
+
+        // 働きが変わらなければ、空白は入れない
+        assert_eq!("です。**強調**", join_lines("です。", "**強調**", omit));
+        assert_eq!("_歴史的_（もはや", join_lines("_歴史的_", "（もはや", omit));
+        assert_eq!("**強調**です", join_lines("**強調**", "です", omit));
+        assert_eq!("すべての*歴史的*", join_lines("すべての", "*歴史的*", omit));
+        assert_eq!("*emphasis* here", join_lines("*emphasis*", "here", omit));
+        assert_eq!("`rustc` を使う", join_lines("`rustc`", "を使う", space));
+
+        // 全角の句読点・括弧の前後には空白を入れない
+        assert_eq!("はい、rustc", join_lines("はい、", "rustc", space));
+        assert_eq!("rustc（コンパイラ）", join_lines("rustc", "（コンパイラ）", space));
+    }
+}
@@ -0,0 +1,62 @@
+//! 整形を抑制するコメント
+//!
+//! 手で整形した段落（詩、ASCII の図、桁を揃えたリストなど）を、
+//! ファイル全体を除外せずに整形の対象外にするための HTML コメントです。
+//!
+//! - `<!-- sembr-ignore -->`: 次のブロックを対象外にする
+//! - `<!-- sembr-ignore-start -->` と `<!-- sembr-ignore-end -->`: その間の行を対象外にする
+//! - `<!-- sembr-ignore-file -->`: ファイル全体を対象外にする（ファイルの先頭に置く）
+//!
+//! コメントは HTML ブロックとして単独の行に置く必要があります。
+//! 段落内のインライン HTML としてのコメントは認識しません。
+
+/// 整形を抑制するコメントの種類
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Suppression {
+    /// `<!-- sembr-ignore-file -->`
+    File,
+    /// `<!-- sembr-ignore -->`
+    Next,
+    /// `<!-- sembr-ignore-start -->`
+    Start,
+    /// `<!-- sembr-ignore-end -->`
+    End,
+}
+
+impl Suppression {
+    /// HTML ブロックの内容が整形を抑制するコメントなら、その種類を返す
+    ///
+    /// # 使用例
+    /// ```rust,ignore
+    /// assert_eq!(Suppression::parse("<!-- sembr-ignore -->\n"), Some(Suppression::Next));
+    /// assert_eq!(Suppression::parse("<!-- date-check -->"), None);
+    /// ```
+    pub(crate) fn parse(html: &str) -> Option<Self> {
+        let comment = html.trim().strip_prefix("<!--")?.strip_suffix("-->")?;
+        match comment.trim() {
+            "sembr-ignore-file" => Some(Suppression::File),
+            "sembr-ignore" => Some(Suppression::Next),
+            "sembr-ignore-start" => Some(Suppression::Start),
+            "sembr-ignore-end" => Some(Suppression::End),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Suppression;
+
+    /// 抑制コメントの認識のテスト
+    #[test]
+    fn test_suppression() {
+        assert_eq!(Some(Suppression::File), Suppression::parse("<!-- sembr-ignore-file -->"));
+        assert_eq!(Some(Suppression::Next), Suppression::parse("<!--sembr-ignore-->\n"));
+        assert_eq!(Some(Suppression::Start), Suppression::parse("  <!-- sembr-ignore-start -->"));
+        assert_eq!(Some(Suppression::End), Suppression::parse("<!-- sembr-ignore-end -->"));
+
+        assert_eq!(None, Suppression::parse("<!-- sembr-ignore-next -->"));
+        assert_eq!(None, Suppression::parse("<!-- date-check: Jan 2025 -->"));
+        assert_eq!(None, Suppression::parse("<!-- sembr-ignore --> trailing"));
+    }
+}
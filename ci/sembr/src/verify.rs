@@ -0,0 +1,214 @@
+//! 整形の前後でレンダリング結果が変わらないことの検証
+//!
+//! 整形は行の区切りだけを変更するはずですが、不具合があると
+//! setext 見出しの結合やインラインコードの途中での分割などで文書が壊れてしまいます。
+//! ファイルを書き換える前に、整形前後のテキストを CommonMark として解析し、
+//! 正規化したイベント列が一致することを確かめます。
+//!
+//! # 正規化の規則
+//! - 連続するテキストとソフト改行を1つのテキストにまとめる
+//! - ソフト改行は空白とみなし、連続する空白を1つにまとめる
+//! - CJK 文字や全角の句読点に隣接する空白は取り除く
+//!   （行の結合で空白を挿入しない位置なので、改行の有無と区別しない）
+//! - その他のイベント（ブロック、インライン要素、コード、HTML など）はそのまま比較する
+
+use std::borrow::Cow;
+use std::ops::Range;
+
+use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+
+use crate::error::{Error, Result};
+use crate::line_ending;
+use crate::markdown::{self, is_block, is_block_end};
+use crate::script::omits_space;
+
+/// 正規化した1つのイベント
+struct Normalized {
+    /// イベントの内容（`Debug` 表現、テキストは正規化済み）
+    event: String,
+    /// イベントを含む最も内側のブロックのソース上の範囲
+    block: Range<usize>,
+}
+
+/// 整形の前後でレンダリング結果が変わらないことを検証する
+///
+/// # 引数
+/// - `old`: 整形前のテキスト
+/// - `new`: 整形後のテキスト
+///
+/// # 戻り値
+/// 一致すれば `Ok(())`。
+/// 異なれば、最初に異なるブロックを含む [`Error::SemanticChange`]。
+///
+/// # 使用例
+/// ```rust
+/// use sembr::verify;
+///
+/// assert!(verify("a\nb\n", "a b\n").is_ok());
+/// assert!(verify("Title\n---\n", "Title ---\n").is_err());
+/// ```
+pub fn verify(old: &str, new: &str) -> Result<()> {
+    // 改行コード、BOM、末尾の改行の有無はレンダリング結果に影響しない
+    let (old, new) = (with_final_newline(old), with_final_newline(new));
+    let old_events = normalize(&old);
+    let new_events = normalize(&new);
+
+    // 最初に異なるイベントの位置（一方が短い場合は、その末尾）
+    let Some(index) = (0..old_events.len().max(new_events.len())).find(|&index| {
+        old_events.get(index).map(|normalized| &normalized.event)
+            != new_events.get(index).map(|normalized| &normalized.event)
+    }) else {
+        return Ok(());
+    };
+
+    let block = |content: &str, events: &[Normalized]| {
+        let range = events
+            .get(index)
+            .or(events.last())
+            .map_or(content.len()..content.len(), |normalized| normalized.block.clone());
+        let line = content[..range.start].matches('\n').count() + 1;
+        (line, content[range].trim_end().to_owned())
+    };
+    let (line, old) = block(&old, &old_events);
+    let (_, new) = block(&new, &new_events);
+    Err(Error::SemanticChange { line, old, new })
+}
+
+/// BOM を取り除き、CRLF を LF に変換し、末尾に改行がなければ追加する
+fn with_final_newline(content: &str) -> Cow<'_, str> {
+    let content = line_ending::strip(content);
+    if content.is_empty() || content.ends_with('\n') {
+        content
+    } else {
+        Cow::Owned(format!("{content}\n"))
+    }
+}
+
+/// テキストを解析し、正規化したイベント列を求める
+fn normalize(content: &str) -> Vec<Normalized> {
+    let mut events: Vec<Normalized> = Vec::new();
+
+    // 開いているブロックのソース上の範囲
+    let mut blocks: Vec<Range<usize>> = Vec::new();
+    // まとめている途中のテキスト
+    let mut text = String::new();
+    // コードブロックの内部（空白を正規化しない）
+    let mut in_code_block = false;
+
+    for (event, range) in Parser::new_ext(content, markdown::parser_options()).into_offset_iter() {
+        // ブロックの外側のイベントは、文書全体に属する
+        let block = blocks.last().cloned().unwrap_or(0..content.len());
+        match event {
+            Event::Text(value) => {
+                text.push_str(&value);
+                continue;
+            }
+            Event::SoftBreak => {
+                text.push(' ');
+                continue;
+            }
+            _ => {}
+        }
+
+        if !text.is_empty() {
+            let text = std::mem::take(&mut text);
+            let text = if in_code_block { text } else { normalize_text(&text) };
+            events.push(Normalized { event: format!("Text({text:?})"), block: block.clone() });
+        }
+
+        // ブロックの開始は、そのブロック自身の範囲に属する
+        let block = match &event {
+            Event::Start(Tag::CodeBlock(_)) => {
+                in_code_block = true;
+                blocks.push(range.clone());
+                range
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                in_code_block = false;
+                blocks.pop().unwrap_or(block)
+            }
+            Event::Start(tag) if is_block(tag) => {
+                blocks.push(range.clone());
+                range
+            }
+            Event::End(tag) if is_block_end(*tag) => blocks.pop().unwrap_or(block),
+            _ => block,
+        };
+        events.push(Normalized { event: format!("{event:?}"), block });
+    }
+
+    events
+}
+
+/// テキストの空白を正規化する
+///
+/// 連続する空白を1つにまとめ、行の結合で省略されうる空白（CJK 文字の隣）を取り除きます。
+fn normalize_text(text: &str) -> String {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let mut normalized = String::new();
+    for (index, word) in words.iter().enumerate() {
+        if index > 0 {
+            let before = normalized.chars().next_back().unwrap();
+            let after = word.chars().next().unwrap();
+            if !omits_space(before) && !omits_space(after) {
+                normalized.push(' ');
+            }
+        }
+        normalized.push_str(word);
+    }
+
+    // 前後の空白は、隣接するインライン要素との区切りとして残す
+    let leading = text.starts_with(char::is_whitespace)
+        && normalized.chars().next().is_some_and(|ch| !omits_space(ch));
+    let trailing = text.ends_with(char::is_whitespace)
+        && normalized.chars().next_back().is_some_and(|ch| !omits_space(ch));
+    format!("{}{normalized}{}", if leading { " " } else { "" }, if trailing { " " } else { "" })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize_text, verify};
+    use crate::error::Error;
+
+    /// 空白の正規化のテスト
+    #[test]
+    fn test_normalize_text() {
+        assert_eq!("a b c", normalize_text("a  b\n c"));
+        assert_eq!("分割しない短い文", normalize_text("分割しない 短い文"));
+        assert_eq!("Rustのcompiler", normalize_text("Rustの compiler"));
+        assert_eq!(" a ", normalize_text(" a\t"));
+        assert_eq!("です。", normalize_text("です。\n"));
+    }
+
+    /// レンダリング結果の比較のテスト
+    #[test]
+    fn test_verify() {
+        // 改行の位置だけが異なる
+        assert!(verify("first. second\nshort\nline\n", "first.\nsecond\nshort line\n").is_ok());
+        assert!(verify("一文目。二文目。\n", "一文目。\n二文目。\n").is_ok());
+        assert!(verify("> quote. here\n", "> quote.\n> here\n").is_ok());
+
+        // setext 見出しを結合すると、見出しが段落になる
+        let Err(Error::SemanticChange { line, old, new }) =
+            verify("# title\n\nTitle\n---\n\nafter\n", "# title\n\nTitle ---\n\nafter\n")
+        else {
+            panic!("expected a semantic change");
+        };
+        assert_eq!(3, line);
+        assert_eq!("Title\n---", old);
+        assert_eq!("Title ---", new);
+
+        // インラインコードの途中での分割は、インラインコードの内容を変える
+        assert!(verify("use `a.  b` here\n", "use `a.\n\nb` here\n").is_err());
+
+        // 末尾の改行の有無、改行コード、BOM は区別しない
+        assert!(verify("<!-- a -->", "<!-- a -->\n").is_ok());
+        assert!(verify("```\na\r\n```\r\n", "\u{FEFF}```\na\n```\n").is_ok());
+
+        // コードブロックの内部の空白は区別する
+        assert!(verify("```\na  b\n```\n", "```\na b\n```\n").is_err());
+
+        // 単語の間の空白がなくなる
+        assert!(verify("do not\nsplit\n", "do notsplit\n").is_err());
+    }
+}
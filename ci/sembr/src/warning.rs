@@ -0,0 +1,65 @@
+//! 整形では解消できない問題についての警告
+//!
+//! 整形を抑制するコメントの誤り（[`crate::suppress`] を参照）、最大行長に収まらない行、
+//! 改行コードの混在を、行番号と種類の組で表します。
+//! CLI のテキスト・JSON・SARIF 出力やエディタの診断は、これを元に作ります。
+
+use serde::Serialize;
+
+/// 警告の種類
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WarningKind {
+    /// `sembr-ignore-start` に対応する `sembr-ignore-end` がない
+    UnterminatedIgnore,
+    /// `sembr-ignore-end` に対応する `sembr-ignore-start` がない
+    UnmatchedIgnoreEnd,
+    /// 文と節の境界で分割しても、最大行長に収まらない
+    LineTooLong,
+    /// LF と CRLF が混在しているため、整形しない
+    MixedLineEndings,
+}
+
+impl WarningKind {
+    /// 警告の識別子（`unterminated-ignore` など）
+    pub fn id(self) -> &'static str {
+        match self {
+            WarningKind::UnterminatedIgnore => "unterminated-ignore",
+            WarningKind::UnmatchedIgnoreEnd => "unmatched-ignore-end",
+            WarningKind::LineTooLong => "line-too-long",
+            WarningKind::MixedLineEndings => "mixed-line-endings",
+        }
+    }
+
+    /// 警告の説明
+    pub fn description(self) -> &'static str {
+        match self {
+            WarningKind::UnterminatedIgnore => {
+                "`sembr-ignore-start` has no matching `sembr-ignore-end`; \
+                 the rest of the file is ignored."
+            }
+            WarningKind::UnmatchedIgnoreEnd => {
+                "`sembr-ignore-end` has no matching `sembr-ignore-start`."
+            }
+            WarningKind::LineTooLong => {
+                "Line exceeds the maximum line length even after breaking at clause boundaries."
+            }
+            WarningKind::MixedLineEndings => {
+                "File mixes LF and CRLF line endings and is left unformatted; \
+                 pass `--line-ending lf` or `--line-ending crlf` to normalize them."
+            }
+        }
+    }
+}
+
+/// 整形では解消できない問題についての警告
+///
+/// 整形を抑制するコメントの誤りや、最大行長に収まらない行、改行コードの混在を表します。
+/// 整形結果には影響しないため、チェックの適合・非適合とは別に報告します。
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Warning {
+    /// 対象の行（1始まり）
+    pub line: usize,
+    /// 警告の種類
+    pub kind: WarningKind,
+}
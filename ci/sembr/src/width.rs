@@ -0,0 +1,59 @@
+//! 行の長さの計測
+//!
+//! `--line-length-limit` と比較する行の長さを計測します。
+//! バイト数で数えると日本語の行は英語の約3倍の長さになってしまうため、
+//! 端末での表示幅、または Unicode スカラー値の数で数えます。
+
+use serde::Deserialize;
+use unicode_width::UnicodeWidthStr;
+
+/// 行の長さを数える単位
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum LengthUnit {
+    /// 端末での表示幅（東アジアの全角文字は2桁として数える）
+    #[default]
+    Columns,
+    /// Unicode スカラー値（`char`）の数
+    Chars,
+}
+
+/// 指定した単位で文字列の長さを数える
+///
+/// # 引数
+/// - `text`: 計測対象の文字列
+/// - `unit`: 長さの単位
+///
+/// # 使用例
+/// ```rust,ignore
+/// assert_eq!(line_length("日本語", LengthUnit::Columns), 6);
+/// assert_eq!(line_length("日本語", LengthUnit::Chars), 3);
+/// ```
+pub fn line_length(text: &str, unit: LengthUnit) -> usize {
+    match unit {
+        LengthUnit::Columns => text.width(),
+        LengthUnit::Chars => text.chars().count(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LengthUnit, line_length};
+
+    /// 単位ごとの長さの計測テスト
+    #[test]
+    fn test_line_length() {
+        // ASCII はどちらの単位でも同じ
+        assert_eq!(12, line_length("do not split", LengthUnit::Columns));
+        assert_eq!(12, line_length("do not split", LengthUnit::Chars));
+
+        // 全角文字は表示幅では2桁
+        assert_eq!(10, line_length("分割しない", LengthUnit::Columns));
+        assert_eq!(5, line_length("分割しない", LengthUnit::Chars));
+
+        // 混在した行
+        assert_eq!(13, line_length("`rustc`の設定", LengthUnit::Columns));
+        assert_eq!(10, line_length("`rustc`の設定", LengthUnit::Chars));
+    }
+}